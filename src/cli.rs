@@ -0,0 +1,524 @@
+//! A module containing the command-line interface, working on key files and serialized
+//! ciphertexts so that the computations can be scripted from a shell.
+use crate::demo;
+use crate::repl;
+use crate::zqz;
+use crate::PARAMS;
+use std::error::Error;
+use std::fs;
+use std::io::{Read, Write};
+use std::rc::Rc;
+use zqz::ciphertext::Ciphertext;
+use zqz::circuit::Circuit;
+use zqz::estimator::{Estimate, Operation};
+use zqz::expr::{self, Value};
+use zqz::header::KeyEncoding;
+use zqz::keys::{EncryptKey, HomomorphicKey};
+use zqz::max::max;
+use zqz::optimizer::optimize;
+use zqz::passphrase::Passphrase;
+use zqz::public::PublicKey;
+use zqz::store::{KeyStore, PASSPHRASE_VAR};
+use zqz::Parameters;
+
+const USAGE: &str = "usage:
+    demo_z8z [--scenario NAME]... [--all] [--non-interactive] [--list]
+                                                      run the demo
+    demo_z8z keygen [--params NAME] [--out DIR] [--encoding ENC] [--force]
+                                                      generate a key set
+    demo_z8z clean [--key DIR]                        delete the unusable key sets
+    demo_z8z pubkey [--params NAME] [--key DIR] [--size N]
+                                                      generate the public key of a key set
+    demo_z8z encrypt [--params NAME] [--key DIR] [--public] M
+                                                      encrypt the integer M to the standard output
+    demo_z8z eval --op OP [--key DIR] A B             evaluate A OP B to the standard output
+    demo_z8z decrypt [--key DIR] A                    decrypt A to the standard output
+    demo_z8z repl [--key DIR]                         evaluate statements interactively
+    demo_z8z run [--key DIR] PROGRAM [X=A]...         evaluate PROGRAM over the inputs A
+    demo_z8z compile [--params NAME] [--out FILE] PROGRAM X...
+                                                      compile PROGRAM into a circuit of inputs X
+    demo_z8z circuit [--key DIR] [--out PREFIX] CIRCUIT A...
+                                                      run the serialized CIRCUIT on the inputs A
+    demo_z8z estimate [--params NAME] [--key DIR] [--op EST] [--runs N]
+                                                      estimate the failure probability of EST
+
+Without any option, the demo runs all its scenarios interactively, waiting for enter between them.
+The demo fails if a decrypted value differs from the expected one.
+
+NAME is one of the parameter sets z8z, z8z-ks or z16z-ks, and defaults to the one of the build.
+DIR is a key store directory, which defaults to $DEMO_Z8Z_KEY_DIR or to the current directory.
+An existing key set is only replaced by keygen with --force, since its ciphertexts would be lost.
+ENC is one of text, binary or compressed, and defaults to binary. The secret keys are encrypted
+under the passphrase given by $DEMO_Z8Z_PASSPHRASE if it is set, which is then needed to load them.
+The key sets deleted by clean are the incomplete or corrupted ones, and the ones of unknown
+parameters.
+The public key is made of N encryptions of zero, by default the minimal number for it to be secure,
+and is saved along the key set. With --public, encrypt only loads the public key and the
+evaluation keys, so that the secret key is not needed.
+OP is one of add, sub, mul or max. A is a ciphertext file, and B is either a ciphertext file or an
+integer. A ciphertext file named - is read from the standard input.
+PROGRAM is a list of statements such as `let f = [0, 1, 4, 1, 0, 1, 4, 1]; f(x) + y`, or @FILE to
+read it from FILE. Each input A is bound to the variable X, and an encrypted result is written to
+the standard output.
+A compiled program is optimized, and written to FILE or to the standard output. Its inputs are the
+variables X in their order, and its output is the value of the last statement.
+The outputs of a circuit are written to the files PREFIX0, PREFIX1..., or to the standard output if
+there is a single one and no PREFIX.
+EST is one of bootstrap, add, mul or max, and defaults to all of them. Each one is run N times,
+1000 by default, with the keys of the parameter set NAME, or of all the parameter sets by default,
+which are loaded from DIR or generated and saved there. The failures are reported with their 95%
+confidence interval, and the noise of the results with its mean, standard deviation and maximum.";
+
+/// The result of a command, whose error is printed to the user.
+pub type CliResult<T> = Result<T, Box<dyn Error>>;
+
+/// Runs the command given by the arguments, without the name of the program. Without a command,
+/// the demo is run with the given options.
+pub fn run(args: &[String]) -> CliResult<()> {
+    let (command, args) = match args.split_first() {
+        Some((command, _)) if command.starts_with("--") && command != "--help" => {
+            return demo::run(args)
+        }
+        Some(split) => split,
+        None => return demo::run(args),
+    };
+    match command.as_str() {
+        "keygen" => keygen(&Args::parse_with_flags(
+            args,
+            &["params", "out", "encoding"],
+            &["force"],
+        )?),
+        "clean" => clean(&Args::parse(args, &["key"])?),
+        "pubkey" => pubkey(&Args::parse(args, &["params", "key", "size"])?),
+        "encrypt" => encrypt(&Args::parse_with_flags(
+            args,
+            &["params", "key"],
+            &["public"],
+        )?),
+        "eval" => eval(&Args::parse(args, &["op", "key"])?),
+        "decrypt" => decrypt(&Args::parse(args, &["key"])?),
+        "repl" => repl(&Args::parse(args, &["key"])?),
+        "run" => run_program(&Args::parse(args, &["key"])?),
+        "compile" => compile(&Args::parse(args, &["params", "out"])?),
+        "circuit" => run_circuit(&Args::parse(args, &["key", "out"])?),
+        "estimate" => estimate(&Args::parse(args, &["params", "key", "op", "runs"])?),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(format!("unknown command `{}`\n\n{}", command, USAGE).into()),
+    }
+}
+
+// The options, the flags and the positional arguments of a command. The options take a value,
+// while the flags do not.
+struct Args {
+    options: Vec<(String, String)>,
+    flags: Vec<String>,
+    positional: Vec<String>,
+}
+
+impl Args {
+    // Parses the arguments of a command accepting the options `names`.
+    fn parse(args: &[String], names: &[&str]) -> CliResult<Args> {
+        Args::parse_with_flags(args, names, &[])
+    }
+
+    // Parses the arguments of a command accepting the options `names` and the flags `flags`.
+    fn parse_with_flags(args: &[String], names: &[&str], flags: &[&str]) -> CliResult<Args> {
+        let mut options = Vec::new();
+        let mut set_flags = Vec::new();
+        let mut positional = Vec::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.strip_prefix("--") {
+                Some(name) if flags.contains(&name) => set_flags.push(name.to_string()),
+                Some(name) if names.contains(&name) => {
+                    let value = iter
+                        .next()
+                        .ok_or_else(|| format!("missing value for --{}", name))?;
+                    options.push((name.to_string(), value.clone()));
+                }
+                Some(name) => return Err(format!("unknown option --{}\n\n{}", name, USAGE).into()),
+                None => positional.push(arg.clone()),
+            }
+        }
+        Ok(Args {
+            options,
+            flags: set_flags,
+            positional,
+        })
+    }
+
+    // Returns whether the flag was given.
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|n| n == name)
+    }
+
+    // Returns the last value given to the option.
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Returns the positional arguments, checking their number.
+    fn positional(&self, expected: usize) -> CliResult<&[String]> {
+        if self.positional.len() != expected {
+            return Err(format!(
+                "expected {} argument(s), {} were given\n\n{}",
+                expected,
+                self.positional.len(),
+                USAGE
+            )
+            .into());
+        }
+        Ok(&self.positional)
+    }
+
+    // Returns the parameters given with --params, or the ones of the build.
+    fn params(&self) -> CliResult<&'static Parameters> {
+        match self.option("params") {
+            None => Ok(&PARAMS),
+            Some(name) => Parameters::from_name(name).ok_or_else(|| {
+                let names: Vec<&str> = zqz::PRESETS.iter().map(|(n, _)| *n).collect();
+                format!(
+                    "unknown parameters `{}`, expected one of {}",
+                    name,
+                    names.join(", ")
+                )
+                .into()
+            }),
+        }
+    }
+
+    // Opens the key store given with the option, or the one of the environment, with the
+    // passphrase of the environment if any.
+    fn store(&self, option: &str) -> CliResult<KeyStore> {
+        let store = match self.option(option) {
+            Some(dir) => KeyStore::new(dir)?,
+            None => KeyStore::from_env()?,
+        };
+        Ok(match std::env::var(PASSPHRASE_VAR) {
+            Ok(passphrase) => store.with_passphrase(Passphrase::new(&passphrase)),
+            Err(_) => store,
+        })
+    }
+}
+
+// Reads a ciphertext file, or the standard input if the path is `-`.
+fn read_bytes(path: &str) -> CliResult<Vec<u8>> {
+    if path == "-" {
+        let mut bytes = Vec::new();
+        std::io::stdin().read_to_end(&mut bytes)?;
+        Ok(bytes)
+    } else {
+        fs::read(path).map_err(|e| format!("{}: {}", path, e).into())
+    }
+}
+
+// Returns the parameters recorded in the header of a serialized ciphertext.
+fn ciphertext_params(path: &str, bytes: &[u8]) -> CliResult<(&'static Parameters, u64)> {
+    let header = Ciphertext::read_header(bytes).map_err(|e| format!("{}: {}", path, e))?;
+    let params = Parameters::from_header(&header.parameters)
+        .ok_or_else(|| format!("{}: the ciphertext uses unknown parameters", path))?;
+    Ok((params, header.key_set_id))
+}
+
+// Writes a serialized ciphertext to the standard output.
+fn write_ciphertext(ct: &Ciphertext) -> CliResult<()> {
+    let mut stdout = std::io::stdout();
+    stdout.write_all(&ct.to_bytes()?)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+fn keygen(args: &Args) -> CliResult<()> {
+    args.positional(0)?;
+    let params = args.params()?;
+    let mut store = args.store("out")?;
+    if let Some(name) = args.option("encoding") {
+        let encoding = KeyEncoding::from_name(name).ok_or_else(|| {
+            let names: Vec<&str> = KeyEncoding::ALL.iter().map(|e| e.name()).collect();
+            format!(
+                "unknown encoding `{}`, expected one of {}",
+                name,
+                names.join(", ")
+            )
+        })?;
+        store = store.with_encoding(encoding);
+    }
+    if store.contains(params) && !args.flag("force") {
+        return Err(format!(
+            "a key set of these parameters already exists in {}, use --force to replace it",
+            store.dir().display()
+        )
+        .into());
+    }
+    let key = EncryptKey::with_params(params);
+    store.save(params, &key)?;
+    eprintln!(
+        "generated the key set {:016x} in {}",
+        key.key_id(),
+        store.dir().display()
+    );
+    Ok(())
+}
+
+fn clean(args: &Args) -> CliResult<()> {
+    args.positional(0)?;
+    let store = args.store("key")?;
+    let presets: Vec<&Parameters> = zqz::PRESETS.iter().map(|(_, p)| *p).collect();
+    for prefix in store.delete_stale(&presets)? {
+        eprintln!(
+            "deleted the key set {} in {}",
+            prefix,
+            store.dir().display()
+        );
+    }
+    Ok(())
+}
+
+fn pubkey(args: &Args) -> CliResult<()> {
+    args.positional(0)?;
+    let params = args.params()?;
+    let size: usize = match args.option("size") {
+        Some(size) => size
+            .parse()
+            .map_err(|_| format!("`{}` is not a non-negative integer", size))?,
+        None => PublicKey::min_size(params),
+    };
+    let store = args.store("key")?;
+    let key = PublicKey::new(&store.load(params)?, size)?;
+    store.save_public_key(&key)?;
+    eprintln!(
+        "generated a public key of {} encryptions of zero in {}",
+        key.size(),
+        store.dir().display()
+    );
+    Ok(())
+}
+
+fn encrypt(args: &Args) -> CliResult<()> {
+    let message = &args.positional(1)?[0];
+    let message: usize = message
+        .parse()
+        .map_err(|_| format!("`{}` is not a non-negative integer", message))?;
+    let store = args.store("key")?;
+    let params = args.params()?;
+    let ct = if args.flag("public") {
+        store.load_public_key(params)?.encrypt(message)?
+    } else {
+        store.load(params)?.encrypt(message)
+    };
+    write_ciphertext(&ct)
+}
+
+fn eval(args: &Args) -> CliResult<()> {
+    let positional = args.positional(2)?;
+    let op = args.option("op").ok_or("missing --op")?;
+
+    // only the evaluation keys are loaded
+    let bytes = read_bytes(&positional[0])?;
+    let (params, id) = ciphertext_params(&positional[0], &bytes)?;
+    let evaluation_key: Rc<HomomorphicKey> =
+        Rc::new(args.store("key")?.load_evaluation_key(params, id)?);
+    let a = Ciphertext::from_bytes(&bytes, &evaluation_key)
+        .map_err(|e| format!("{}: {}", positional[0], e))?;
+
+    let res = match positional[1].parse::<usize>() {
+        Ok(constant) => match op {
+            "add" => &a + constant,
+            "sub" => &a - constant,
+            "mul" => &a * constant,
+            "max" => max(&a, constant),
+            _ => return Err(format!("unknown operation `{}`", op).into()),
+        },
+        Err(_) => {
+            let b = Ciphertext::from_bytes(&read_bytes(&positional[1])?, &evaluation_key)
+                .map_err(|e| format!("{}: {}", positional[1], e))?;
+            match op {
+                "add" => &a + &b,
+                "sub" => &a - &b,
+                "mul" => &a * &b,
+                "max" => max(&a, &b),
+                _ => return Err(format!("unknown operation `{}`", op).into()),
+            }
+        }
+    };
+    write_ciphertext(&res)
+}
+
+fn decrypt(args: &Args) -> CliResult<()> {
+    let path = &args.positional(1)?[0];
+    let bytes = read_bytes(path)?;
+    let (params, _) = ciphertext_params(path, &bytes)?;
+    let key = args.store("key")?.load(params)?;
+    let ct = Ciphertext::from_bytes(&bytes, key.evaluation_key())
+        .map_err(|e| format!("{}: {}", path, e))?;
+    println!("{}", key.decrypt(&ct));
+    Ok(())
+}
+
+fn repl(args: &Args) -> CliResult<()> {
+    args.positional(0)?;
+    let key = args.store("key")?.load_or_generate()?;
+    repl::run(&key)
+}
+
+fn run_program(args: &Args) -> CliResult<()> {
+    let (program, inputs) = args
+        .positional
+        .split_first()
+        .ok_or_else(|| format!("missing program\n\n{}", USAGE))?;
+    let program = read_program(program)?;
+
+    // the evaluation keys are the ones of the first input, the other inputs must share them
+    let mut evaluation_key: Option<Rc<HomomorphicKey>> = None;
+    let mut ciphertexts = Vec::new();
+    for input in inputs.iter() {
+        let (name, path) = input
+            .split_once('=')
+            .ok_or_else(|| format!("`{}` is not of the form X=A", input))?;
+        let bytes = read_bytes(path)?;
+        let key = match &evaluation_key {
+            Some(key) => key.clone(),
+            None => {
+                let (params, id) = ciphertext_params(path, &bytes)?;
+                let key = Rc::new(args.store("key")?.load_evaluation_key(params, id)?);
+                evaluation_key = Some(key.clone());
+                key
+            }
+        };
+        let ct = Ciphertext::from_bytes(&bytes, &key).map_err(|e| format!("{}: {}", path, e))?;
+        ciphertexts.push((name, ct));
+    }
+
+    match expr::eval_program(&program, &ciphertexts)? {
+        Some(Value::Encrypted(ct)) => write_ciphertext(&ct),
+        Some(value) => {
+            println!("{}", value);
+            Ok(())
+        }
+        None => Err("the program is empty".into()),
+    }
+}
+
+// Returns the source of a program, read from FILE if it is given as @FILE.
+fn read_program(program: &str) -> CliResult<String> {
+    match program.strip_prefix('@') {
+        Some(path) => fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e).into()),
+        None => Ok(program.to_string()),
+    }
+}
+
+fn compile(args: &Args) -> CliResult<()> {
+    let (program, inputs) = args
+        .positional
+        .split_first()
+        .ok_or_else(|| format!("missing program\n\n{}", USAGE))?;
+    let program = read_program(program)?;
+    let params = args.params()?;
+    let inputs: Vec<&str> = inputs.iter().map(|name| name.as_str()).collect();
+    let (circuit, report) = optimize(&expr::compile(&program, &inputs, params.modulo)?);
+    circuit.check_params(params)?;
+    let bytes = circuit.to_bytes();
+    match args.option("out") {
+        Some(path) => fs::write(path, bytes).map_err(|e| format!("{}: {}", path, e))?,
+        None => {
+            let mut stdout = std::io::stdout();
+            stdout.write_all(&bytes)?;
+            stdout.flush()?;
+        }
+    }
+    eprintln!("compiled the circuit: {}", report);
+    Ok(())
+}
+
+fn run_circuit(args: &Args) -> CliResult<()> {
+    let (path, inputs) = args
+        .positional
+        .split_first()
+        .ok_or_else(|| format!("missing circuit\n\n{}", USAGE))?;
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let circuit = Circuit::from_bytes(&bytes).map_err(|e| format!("{}: {}", path, e))?;
+    if inputs.len() != circuit.nb_inputs() {
+        return Err(format!(
+            "the circuit takes {} input(s), {} were given",
+            circuit.nb_inputs(),
+            inputs.len()
+        )
+        .into());
+    }
+    if circuit.outputs().is_empty() {
+        return Err(format!("{}: the circuit has no output", path).into());
+    }
+    if circuit.outputs().len() > 1 && args.option("out").is_none() {
+        return Err("the circuit has several outputs, use --out PREFIX".into());
+    }
+
+    // only the evaluation keys of the first input are loaded, the other inputs must share them
+    let mut evaluation_key: Option<Rc<HomomorphicKey>> = None;
+    let mut ciphertexts = Vec::with_capacity(inputs.len());
+    for input in inputs.iter() {
+        let bytes = read_bytes(input)?;
+        let key = match &evaluation_key {
+            Some(key) => key.clone(),
+            None => {
+                let (params, id) = ciphertext_params(input, &bytes)?;
+                circuit.check_params(params)?;
+                let key = Rc::new(args.store("key")?.load_evaluation_key(params, id)?);
+                evaluation_key = Some(key.clone());
+                key
+            }
+        };
+        let ct = Ciphertext::from_bytes(&bytes, &key).map_err(|e| format!("{}: {}", input, e))?;
+        ciphertexts.push(ct);
+    }
+
+    let outputs = circuit.run(&ciphertexts)?;
+    match args.option("out") {
+        Some(prefix) => {
+            for (i, ct) in outputs.iter().enumerate() {
+                let path = format!("{}{}", prefix, i);
+                fs::write(&path, ct.to_bytes()?).map_err(|e| format!("{}: {}", path, e))?;
+            }
+            Ok(())
+        }
+        None => write_ciphertext(&outputs[0]),
+    }
+}
+
+fn estimate(args: &Args) -> CliResult<()> {
+    args.positional(0)?;
+    let params: Vec<&'static Parameters> = match args.option("params") {
+        Some(_) => vec![args.params()?],
+        None => zqz::PRESETS.iter().map(|(_, p)| *p).collect(),
+    };
+    let operations = match args.option("op") {
+        Some(name) => {
+            let operation = Operation::from_name(name)
+                .ok_or_else(|| format!("unknown operation `{}`", name))?;
+            vec![operation]
+        }
+        None => Operation::ALL.to_vec(),
+    };
+    let runs: usize = match args.option("runs") {
+        Some(runs) => runs
+            .parse()
+            .map_err(|_| format!("`{}` is not a non-negative integer", runs))?,
+        None => 1000,
+    };
+
+    let store = args.store("key")?;
+    for params in params {
+        println!("{}:", params.name().unwrap_or("unknown parameters"));
+        let key = store.load_or_generate_with_params(params)?;
+        for operation in operations.iter() {
+            println!("  {}", Estimate::new(&key, *operation, runs));
+        }
+    }
+    Ok(())
+}
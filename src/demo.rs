@@ -0,0 +1,281 @@
+//! A module containing the demonstration of computations over encrypted Z/qZ numbers, organized
+//! as a list of scenarios which can be run interactively, or from a script or a CI job.
+use crate::cli::CliResult;
+use crate::measure_duration;
+use crate::zqz;
+use colored::Colorize;
+use zqz::circuit::Circuit;
+use zqz::keys::EncryptKey;
+use zqz::linear::{bootstrap_cost, matvec};
+use zqz::max::max;
+use zqz::optimizer::optimize;
+use zqz::store::KeyStore;
+
+// The expected and the decrypted values of a scenario.
+type Outcome = (Vec<usize>, Vec<usize>);
+
+/// A scenario of the demo, computing a value over encrypted numbers.
+pub struct Scenario {
+    pub name: &'static str,
+    pub description: &'static str,
+    // Runs the scenario, and returns its outcome.
+    run: fn(&EncryptKey) -> CliResult<Outcome>,
+}
+
+/// The scenarios of the demo, in the order of the interactive demo.
+pub static SCENARIOS: [Scenario; 5] = [
+    Scenario {
+        name: "circuit",
+        description: "Circuit evaluation: (4 * 7 + 5 + 2) * 3",
+        run: circuit,
+    },
+    Scenario {
+        name: "max",
+        description: "Max computation: max(4, 7)",
+        run: max_computation,
+    },
+    Scenario {
+        name: "cube",
+        description: "Function evaluation: f(x) = x^3 with x = 5",
+        run: cube,
+    },
+    Scenario {
+        name: "optimized",
+        description: "Optimized circuit evaluation: (4 * 7 + 5 + 2) * 3",
+        run: optimized_circuit,
+    },
+    Scenario {
+        name: "linear",
+        description: "Matrix-vector product: [[1, 1], [2, 3]] x (4, 7)",
+        run: linear,
+    },
+];
+
+/// Runs the demo with the given options: `--scenario NAME` (repeatable) or `--all` to select the
+/// scenarios, `--non-interactive` not to wait for the user between the scenarios, and `--list` to
+/// list the scenarios. Without any option, all the scenarios are run interactively.
+///
+/// An error is returned if a decrypted value differs from the expected one.
+pub fn run(args: &[String]) -> CliResult<()> {
+    let mut names: Vec<&str> = Vec::new();
+    let mut all = args.is_empty();
+    let mut interactive = true;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--scenario" => {
+                let name = iter.next().ok_or("missing value for --scenario")?;
+                names.push(name.as_str());
+            }
+            "--all" => all = true,
+            "--non-interactive" => interactive = false,
+            "--list" => {
+                for scenario in SCENARIOS.iter() {
+                    println!("{:<10} {}", scenario.name, scenario.description);
+                }
+                return Ok(());
+            }
+            _ => return Err(format!("unknown demo option `{}`", arg).into()),
+        }
+    }
+    let scenarios: Vec<&Scenario> = if all {
+        SCENARIOS.iter().collect()
+    } else {
+        names
+            .iter()
+            .map(|name| {
+                SCENARIOS
+                    .iter()
+                    .find(|s| s.name == *name)
+                    .ok_or_else(|| format!("unknown scenario `{}`, see --list", name))
+            })
+            .collect::<Result<_, _>>()?
+    };
+    if scenarios.is_empty() {
+        return Err("no scenario selected, use --scenario NAME or --all".into());
+    }
+
+    // Generating / Loading keys
+    measure_duration!("1. Key Loading...",[
+        let sk = KeyStore::from_env().and_then(|store| store.load_or_generate())?;
+    ]);
+    pause(interactive);
+
+    let mut failures: Vec<&str> = Vec::new();
+    for (i, scenario) in scenarios.iter().enumerate() {
+        println!("{}", format!("{}. {}", i + 2, scenario.description).bold());
+        let (expected, res) = (scenario.run)(&sk)?;
+
+        // Result
+        let s_res = format!("{} mod {}", format_values(&res), sk.params().modulo);
+        println!(
+            "-> Output (should be {}): {}",
+            format_values(&expected),
+            s_res.blue().bold()
+        );
+        if res != expected {
+            println!("{}", "-> Mismatch!".red().bold());
+            failures.push(scenario.name);
+        }
+
+        if i + 1 < scenarios.len() {
+            pause(interactive);
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} scenario(s) failed: {}",
+            failures.len(),
+            failures.join(", ")
+        )
+        .into())
+    }
+}
+
+// Waits for the user to press enter, in interactive mode.
+fn pause(interactive: bool) {
+    if interactive {
+        let mut reader_buffer = String::new();
+        std::io::stdin().read_line(&mut reader_buffer).unwrap();
+    }
+}
+
+// Formats a single value as is, and several ones as a tuple.
+fn format_values(values: &[usize]) -> String {
+    let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    match values.len() {
+        1 => values[0].clone(),
+        _ => format!("({})", values.join(", ")),
+    }
+}
+
+fn circuit(sk: &EncryptKey) -> CliResult<Outcome> {
+    // Encryption
+    measure_duration!("Encryption...", [
+        let ct1 = sk.encrypt(4);
+        let ct2 = sk.encrypt(7);
+        let ct3 = sk.encrypt(5);
+    ]);
+
+    // Homomorphic computation
+    measure_duration!("Homomorphic computation...", [
+        let mut ct_res = &ct1 * &ct2; // res <- m1 * m2
+        ct_res = &ct_res + &ct3; // res <- res + m3
+        ct_res = &ct_res + 2; // res <- res + 2
+        ct_res = &ct_res * 3; // res <- res * 3
+    ]);
+
+    // Decryption
+    measure_duration!("Decryption...", [
+        let res = sk.decrypt(&ct_res);
+    ]);
+
+    Ok((vec![105 % sk.params().modulo], vec![res]))
+}
+
+fn max_computation(sk: &EncryptKey) -> CliResult<Outcome> {
+    // Encryption
+    measure_duration!("Encryption...", [
+        let ct1 = sk.encrypt(4);
+        let ct2 = sk.encrypt(7);
+    ]);
+
+    // Max computation
+    measure_duration!("Homomorphic computation...", [
+        let ct_max_ct = max(&ct1, &ct2); // res <- max(m1, m2)
+    ]);
+
+    // Decryption
+    measure_duration!("Decryption...", [
+        let res = sk.decrypt(&ct_max_ct);
+    ]);
+
+    Ok((vec![7], vec![res]))
+}
+
+fn cube(sk: &EncryptKey) -> CliResult<Outcome> {
+    // Encryption
+    measure_duration!("Encryption...", [
+        let ct3 = sk.encrypt(5);
+    ]);
+
+    // Function evaluation
+    measure_duration!("Homomorphic computation...", [
+        let ct_ev = ct3.eval(|x| f64::powi(x, 3));
+    ]);
+
+    // Decryption
+    measure_duration!("Decryption...", [
+        let res = sk.decrypt(&ct_ev);
+    ]);
+
+    Ok((vec![5 * 5 * 5 % sk.params().modulo], vec![res]))
+}
+
+fn optimized_circuit(sk: &EncryptKey) -> CliResult<Outcome> {
+    // Circuit
+    let mut circuit = Circuit::with_modulo(sk.params().modulo);
+    let a = circuit.input();
+    let b = circuit.input();
+    let c = circuit.input();
+    let two = circuit.constant(2);
+    let three = circuit.constant(3);
+    let res = circuit.mul(a, b); // res <- m1 * m2
+    let res = circuit.add(res, c); // res <- res + m3
+    let res = circuit.add(res, two); // res <- res + 2
+    let res = circuit.mul(res, three); // res <- res * 3
+    circuit.output(res);
+
+    // Optimization
+    measure_duration!("Optimization...", [
+        let (optimized, report) = optimize(&circuit);
+        println!("-> {}", report);
+    ]);
+
+    // Encryption
+    measure_duration!("Encryption...", [
+        let inputs = [sk.encrypt(4), sk.encrypt(7), sk.encrypt(5)];
+    ]);
+
+    // Homomorphic computation
+    measure_duration!("Homomorphic computation...", [
+        let outputs = optimized.run(&inputs)?;
+    ]);
+
+    // Decryption
+    measure_duration!("Decryption...", [
+        let res = sk.decrypt(&outputs[0]);
+    ]);
+
+    Ok((circuit.run_clear(&[4, 7, 5]), vec![res]))
+}
+
+fn linear(sk: &EncryptKey) -> CliResult<Outcome> {
+    let matrix = [vec![1, 1], vec![2, 3]];
+    let costs: Vec<usize> = matrix
+        .iter()
+        .map(|row| bootstrap_cost(row, sk.params()))
+        .collect();
+    println!("-> Bootstraps of the rows: {}", format_values(&costs));
+
+    // Encryption
+    measure_duration!("Encryption...", [
+        let cts = [sk.encrypt(4), sk.encrypt(7)];
+    ]);
+
+    // Homomorphic computation
+    measure_duration!("Homomorphic computation...", [
+        let ct_res = matvec(&matrix, &cts); // res <- matrix * (m1, m2)
+    ]);
+
+    // Decryption
+    measure_duration!("Decryption...", [
+        let res: Vec<usize> = ct_res.iter().map(|ct| sk.decrypt(ct)).collect();
+    ]);
+
+    let q = sk.params().modulo;
+    Ok((vec![(4 + 7) % q, (2 * 4 + 3 * 7) % q], res))
+}
@@ -0,0 +1,74 @@
+//! A module containing an interactive interpreter, evaluating statements over encrypted numbers
+//! with the language of `zqz::expr`.
+use crate::cli::CliResult;
+use crate::zqz;
+use colored::Colorize;
+use std::io::{BufRead, Write};
+use std::time::Instant;
+use zqz::expr::{Env, Statement};
+use zqz::keys::EncryptKey;
+
+const HELP: &str = "Statements are separated by `;`, e.g.
+    let a = enc(4); let b = enc(7); dec(max(a * b, 3))
+The operators are +, - and *, and the functions are enc(m), dec(c) and max(a, b).
+Each statement is followed by its duration and the number of bootstraps it needed.
+Type `help` to print this message, and `quit` or Ctrl-D to exit.";
+
+/// Runs the interpreter, reading lines from the standard input until its end or `quit`.
+pub fn run(sk: &EncryptKey) -> CliResult<()> {
+    println!("Type `help` for help.");
+    let mut env = Env::with_key(sk);
+    let stdin = std::io::stdin();
+    prompt()?;
+    for line in stdin.lock().lines() {
+        match line?.trim() {
+            "quit" | "exit" => break,
+            "help" => println!("{}", HELP),
+            "" => {}
+            source => match zqz::expr::parse(source) {
+                Ok(statements) => {
+                    // the statements following an error are not executed
+                    for statement in statements.iter() {
+                        if !execute(&mut env, statement) {
+                            break;
+                        }
+                    }
+                }
+                Err(e) => println!("{}", format!("error: {}", e).red()),
+            },
+        }
+        prompt()?;
+    }
+    Ok(())
+}
+
+fn prompt() -> CliResult<()> {
+    print!("> ");
+    std::io::stdout().flush()?;
+    Ok(())
+}
+
+// Executes a statement and prints its value along with its cost. Returns false on error.
+fn execute(env: &mut Env, statement: &Statement) -> bool {
+    let bootstraps = zqz::ciphertext::bootstrap_count();
+    let now = Instant::now();
+    let res = env.execute(statement);
+    let time = now.elapsed().as_millis() as f64 / 1000.;
+    let bootstraps = zqz::ciphertext::bootstrap_count() - bootstraps;
+    let cost = format!("({} s, {} bootstrap(s))", time, bootstraps);
+
+    match (res, statement) {
+        (Ok(value), Statement::Let(name, _)) => {
+            println!("{} = {} {}", name, value, cost.green());
+            true
+        }
+        (Ok(value), Statement::Expr(_)) => {
+            println!("{} {}", value.to_string().blue().bold(), cost.green());
+            true
+        }
+        (Err(e), _) => {
+            println!("{}", format!("error: {}", e).red());
+            false
+        }
+    }
+}
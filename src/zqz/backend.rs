@@ -0,0 +1,168 @@
+//! A module containing the backends performing the cryptographic operations of the ciphertexts.
+//!
+//! The LWE backend uses the LWE ciphertexts, bootstraps and keyswitches of concrete. The plaintext
+//! backend stores the messages in the clear, along with the encoders concrete would use so that
+//! the padding is spent in the same way, and computes the bootstraps by applying their functions
+//! to the messages: the same code runs instantly, which allows to test the logic of encrypted
+//! programs, and to compare the results of the LWE backend with the expected ones.
+use crate::zqz;
+use concrete::crypto_api;
+use zqz::keys::HomomorphicKey;
+
+/// The backend of a key set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// The LWE ciphertexts of concrete.
+    Lwe,
+    /// The messages stored in the clear, see the module documentation.
+    Plaintext,
+}
+
+/// A message of the plaintext backend, with the encoder of the equivalent LWE ciphertext.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlainCiphertext {
+    value: f64,
+    encoder: crypto_api::Encoder,
+}
+
+impl PlainCiphertext {
+    // Encodes a message with the encoder.
+    pub(super) fn encode(value: f64, encoder: &crypto_api::Encoder) -> PlainCiphertext {
+        PlainCiphertext {
+            value,
+            encoder: encoder.clone(),
+        }
+    }
+
+    // Returns the message.
+    pub(super) fn decode(&self) -> f64 {
+        self.value
+    }
+}
+
+/// A ciphertext of one of the backends.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RawCiphertext {
+    Lwe(crypto_api::LWE),
+    Plaintext(PlainCiphertext),
+}
+
+// Panics on an operation between ciphertexts of different backends, which cannot share a key set.
+fn mixed_backends() -> ! {
+    panic!("the ciphertexts belong to different backends")
+}
+
+impl RawCiphertext {
+    // Returns the LWE ciphertext, e.g. to serialize it, which a message of the plaintext backend
+    // does not have.
+    pub(super) fn as_lwe(&self) -> Option<&crypto_api::LWE> {
+        match self {
+            RawCiphertext::Lwe(ct) => Some(ct),
+            RawCiphertext::Plaintext(_) => None,
+        }
+    }
+
+    // Returns the encoder of the message.
+    pub(super) fn encoder(&self) -> &crypto_api::Encoder {
+        match self {
+            RawCiphertext::Lwe(ct) => &ct.encoder,
+            RawCiphertext::Plaintext(ct) => &ct.encoder,
+        }
+    }
+
+    // Adds two ciphertexts by spending one bit of padding: the interval of the encoder doubles.
+    pub(super) fn add_with_padding_exact(&self, other: &RawCiphertext) -> RawCiphertext {
+        let mut res = self.clone();
+        res.add_with_padding_exact_inplace(other);
+        res
+    }
+
+    pub(super) fn add_with_padding_exact_inplace(&mut self, other: &RawCiphertext) {
+        match (self, other) {
+            (RawCiphertext::Lwe(a), RawCiphertext::Lwe(b)) => {
+                a.add_with_padding_exact_inplace(b).unwrap()
+            }
+            (RawCiphertext::Plaintext(a), RawCiphertext::Plaintext(b)) => {
+                a.value += b.value;
+                a.encoder.o += b.encoder.o;
+                a.encoder.delta *= 2.;
+                a.encoder.nb_bit_padding -= 1;
+            }
+            _ => mixed_backends(),
+        }
+    }
+
+    // Subtracts two ciphertexts by spending one bit of padding.
+    pub(super) fn sub_with_padding_exact(&self, other: &RawCiphertext) -> RawCiphertext {
+        let mut res = self.clone();
+        res.sub_with_padding_exact_inplace(other);
+        res
+    }
+
+    pub(super) fn sub_with_padding_exact_inplace(&mut self, other: &RawCiphertext) {
+        match (self, other) {
+            (RawCiphertext::Lwe(a), RawCiphertext::Lwe(b)) => {
+                a.sub_with_padding_exact_inplace(b).unwrap()
+            }
+            (RawCiphertext::Plaintext(a), RawCiphertext::Plaintext(b)) => {
+                a.value -= b.value;
+                a.encoder.o -= b.encoder.o + b.encoder.delta;
+                a.encoder.delta *= 2.;
+                a.encoder.nb_bit_padding -= 1;
+            }
+            _ => mixed_backends(),
+        }
+    }
+
+    // Adds a constant to the message, translating the interval of the encoder.
+    pub(super) fn add_constant_dynamic_encoder(&self, constant: f64) -> RawCiphertext {
+        match self {
+            RawCiphertext::Lwe(ct) => {
+                RawCiphertext::Lwe(ct.add_constant_dynamic_encoder(constant).unwrap())
+            }
+            RawCiphertext::Plaintext(ct) => {
+                let mut res = ct.clone();
+                res.value += constant;
+                res.encoder.o += constant;
+                RawCiphertext::Plaintext(res)
+            }
+        }
+    }
+
+    // Keyswitches the ciphertext, which leaves a message of the plaintext backend unchanged.
+    pub(super) fn keyswitch(&self, ksk: &crypto_api::LWEKSK) -> RawCiphertext {
+        match self {
+            RawCiphertext::Lwe(ct) => RawCiphertext::Lwe(ct.keyswitch(ksk).unwrap()),
+            RawCiphertext::Plaintext(_) => self.clone(),
+        }
+    }
+
+    // Bootstraps the ciphertext with the function, and keyswitches the result back to the lwe
+    // secret key if the parameters need it. The plaintext backend applies the function to the
+    // message, and reduces the result into the interval of the output encoder.
+    pub(super) fn bootstrap<F: Fn(f64) -> f64>(
+        &self,
+        evaluation_key: &HomomorphicKey,
+        func: F,
+        encoder: &crypto_api::Encoder,
+    ) -> RawCiphertext {
+        match (self, &evaluation_key.lwe) {
+            (RawCiphertext::Lwe(ct), Some(keys)) => {
+                let res = ct
+                    .bootstrap_with_function(&keys.bootstrapping, func, encoder)
+                    .unwrap();
+                if evaluation_key.params.with_ks {
+                    RawCiphertext::Lwe(res.keyswitch(&keys.keyswitching).unwrap())
+                } else {
+                    RawCiphertext::Lwe(res)
+                }
+            }
+            (RawCiphertext::Plaintext(ct), None) => {
+                let y = func(ct.value);
+                let value = encoder.o + (y - encoder.o).rem_euclid(encoder.delta);
+                RawCiphertext::Plaintext(PlainCiphertext::encode(value, encoder))
+            }
+            _ => mixed_backends(),
+        }
+    }
+}
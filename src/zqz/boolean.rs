@@ -0,0 +1,86 @@
+//! A module containing an encrypted boolean structure.
+use crate::zqz;
+use std::ops::{BitAnd, BitOr, BitXor, Not};
+use zqz::ciphertext::{bs_ks, Ciphertext};
+
+/// An encrypted boolean, i.e. a ciphertext whose message is known to be either 0 or 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoolCiphertext(pub(super) Ciphertext);
+
+impl BoolCiphertext {
+    /// Converts a ciphertext to an encrypted boolean, which is true iff the message is not null.
+    pub fn from_ciphertext(ct: &Ciphertext) -> BoolCiphertext {
+        BoolCiphertext(ct.eval(|x| (x != 0.) as usize as f64))
+    }
+
+    /// Returns the underlying 0/1 ciphertext.
+    pub fn into_ciphertext(self) -> Ciphertext {
+        self.0
+    }
+
+    // Adds the two encrypted booleans using the padding, and applies `f` on the sum which lies in
+    // {0, 1, 2} once the operands are encoded like fresh encryptions.
+    fn combine<F: Fn(usize) -> bool>(&self, other: &BoolCiphertext, f: F) -> BoolCiphertext {
+        self.0.check_key(&other.0);
+        let lhs = self.0.canonical();
+        let rhs = other.0.canonical();
+
+        // addition
+        let sum = lhs.ciphertext.add_with_padding_exact(&rhs.ciphertext);
+
+        // boolean function
+        let res = bs_ks(
+            &sum,
+            &self.0.evaluation_key,
+            |x| f(x.round() as usize) as usize as f64,
+            &self.0.fresh_encoder(),
+        );
+
+        BoolCiphertext(Ciphertext {
+            ciphertext: res,
+            evaluation_key: self.0.evaluation_key.clone(),
+        })
+    }
+}
+
+impl From<BoolCiphertext> for Ciphertext {
+    fn from(ct: BoolCiphertext) -> Self {
+        ct.into_ciphertext()
+    }
+}
+
+// Computes the logical and of two encrypted booleans using the `&` operator.
+impl BitAnd<&BoolCiphertext> for &BoolCiphertext {
+    type Output = BoolCiphertext;
+
+    fn bitand(self, other: &BoolCiphertext) -> Self::Output {
+        self.combine(other, |s| s == 2)
+    }
+}
+
+// Computes the logical or of two encrypted booleans using the `|` operator.
+impl BitOr<&BoolCiphertext> for &BoolCiphertext {
+    type Output = BoolCiphertext;
+
+    fn bitor(self, other: &BoolCiphertext) -> Self::Output {
+        self.combine(other, |s| s >= 1)
+    }
+}
+
+// Computes the logical xor of two encrypted booleans using the `^` operator.
+impl BitXor<&BoolCiphertext> for &BoolCiphertext {
+    type Output = BoolCiphertext;
+
+    fn bitxor(self, other: &BoolCiphertext) -> Self::Output {
+        self.combine(other, |s| s == 1)
+    }
+}
+
+// Computes the negation of an encrypted boolean using the `!` operator.
+impl Not for &BoolCiphertext {
+    type Output = BoolCiphertext;
+
+    fn not(self) -> Self::Output {
+        BoolCiphertext(self.0.eval(|x| 1. - x))
+    }
+}
@@ -1,48 +1,411 @@
 //! A module containing a ciphertext structure.
 use crate::zqz;
-use crate::PARAMS;
 use concrete::crypto_api;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::fmt;
 use std::ops::{Add, AddAssign, Mul, MulAssign, Sub, SubAssign};
 use std::rc::Rc;
-use zqz::keys::HomomorphicKey;
+use zqz::backend::{Backend, RawCiphertext};
+use zqz::header::ParametersHeader;
+use zqz::keys::{ConversionKey, HomomorphicKey};
+use zqz::lut::LookupTable;
 use zqz::max::Max;
+use zqz::Parameters;
+
+/// The current version of the serialization format of the ciphertexts.
+pub const CIPHERTEXT_VERSION: u32 = 1;
+
+const CIPHERTEXT_MAGIC: &[u8] = b"demo_z8z-ct\n";
+
+thread_local! {
+    // The number of bootstraps performed by the thread, see `bootstrap_count`.
+    static BOOTSTRAPS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Returns the number of bootstraps performed by the current thread so far, e.g. to measure the
+/// cost of a computation by difference.
+pub fn bootstrap_count() -> usize {
+    BOOTSTRAPS.with(|count| count.get())
+}
 
 /// An encrypted message.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct Ciphertext {
-    pub(super) ciphertext: crypto_api::LWE,
+    pub(super) ciphertext: RawCiphertext,
     pub(super) evaluation_key: Rc<HomomorphicKey>,
 }
 
-fn bs_ks<F: Fn(f64) -> f64>(
-    ciphertext: &crypto_api::LWE,
-    bootstrapping_key: &crypto_api::LWEBSK,
+// Two ciphertexts are equal if they are encrypted with the same key set, which is checked with
+// its identifier instead of comparing the whole evaluation keys.
+impl PartialEq for Ciphertext {
+    fn eq(&self, other: &Ciphertext) -> bool {
+        self.key_id() == other.key_id() && self.ciphertext == other.ciphertext
+    }
+}
+
+/// The header of a serialized ciphertext, recording the key set it was encrypted with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CiphertextHeader {
+    pub version: u32,
+    pub key_set_id: u64,
+    pub parameters: ParametersHeader,
+}
+
+/// The errors raised when deserializing a ciphertext.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CiphertextError {
+    /// The bytes are not a serialized ciphertext.
+    InvalidFormat(String),
+    /// The ciphertext was serialized with an unsupported version of the format.
+    UnsupportedVersion(u32),
+    /// The ciphertext was encrypted with another key set.
+    KeySetMismatch { expected: u64, found: u64 },
+    /// The ciphertexts of the plaintext backend are not meant to be stored.
+    PlaintextBackend,
+}
+
+impl fmt::Display for CiphertextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CiphertextError::InvalidFormat(reason) => write!(f, "invalid ciphertext: {}", reason),
+            CiphertextError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported ciphertext format version {} (expected {})",
+                version, CIPHERTEXT_VERSION
+            ),
+            CiphertextError::KeySetMismatch { expected, found } => write!(
+                f,
+                "the ciphertext was encrypted with the key set {:016x} instead of {:016x}",
+                found, expected
+            ),
+            CiphertextError::PlaintextBackend => write!(
+                f,
+                "the ciphertexts of the plaintext backend can not be serialized"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CiphertextError {}
+
+pub(super) fn bs_ks<F: Fn(f64) -> f64>(
+    ciphertext: &RawCiphertext,
+    evaluation_key: &HomomorphicKey,
     func: F,
     encoder: &crypto_api::Encoder,
-    keyswitching_key: &crypto_api::LWEKSK,
-) -> crypto_api::LWE {
-    let res = ciphertext
-        .bootstrap_with_function(bootstrapping_key, func, encoder)
-        .unwrap();
-
-    if PARAMS.with_ks {
-        let res_ks = res.keyswitch(keyswitching_key).unwrap();
-        return res_ks;
-    } else {
-        return res;
+) -> RawCiphertext {
+    BOOTSTRAPS.with(|count| count.set(count.get() + 1));
+    ciphertext.bootstrap(evaluation_key, func, encoder)
+}
+
+// Sums the given ciphertexts by spending their padding, and only bootstraps (modulo) when the
+// padding budget is exhausted or when two operands are not encoded alike. The output is always
+// refreshed to a fresh encoder.
+pub(super) fn sum_with_padding(
+    mut terms: Vec<RawCiphertext>,
+    evaluation_key: &HomomorphicKey,
+) -> RawCiphertext {
+    assert!(!terms.is_empty(), "cannot sum an empty list of ciphertexts");
+    let params = evaluation_key.params;
+    let q = params.modulo;
+    let encoder = zqz::utils::new_encoder(params);
+    let refresh = |ct: RawCiphertext| {
+        if ct.encoder().nb_bit_padding == params.nb_bit_padding {
+            ct
+        } else {
+            bs_ks(
+                &ct,
+                evaluation_key,
+                |x| zqz::utils::round_modulo(x, q),
+                &encoder,
+            )
+        }
+    };
+
+    // pairwise addition, level by level
+    while terms.len() > 1 {
+        let mut next = Vec::with_capacity(terms.len().div_ceil(2));
+        let mut iter = terms.into_iter();
+        while let Some(mut left) = iter.next() {
+            if let Some(mut right) = iter.next() {
+                // the sum must keep at least one bit of padding for the bootstrap
+                if left.encoder().nb_bit_padding < 2
+                    || left.encoder().nb_bit_padding != right.encoder().nb_bit_padding
+                {
+                    left = refresh(left);
+                    right = refresh(right);
+                }
+                left.add_with_padding_exact_inplace(&right);
+            }
+            next.push(left);
+        }
+        terms = next;
     }
+
+    // modulo
+    bs_ks(
+        &terms[0],
+        evaluation_key,
+        |x| zqz::utils::round_modulo(x, q),
+        &encoder,
+    )
 }
 
 impl Ciphertext {
-    #[allow(dead_code)]
+    /// Returns the identifier of the key set the message was encrypted with.
+    pub fn key_id(&self) -> u64 {
+        self.evaluation_key.id
+    }
+
+    /// Returns the parameters of the key set the message was encrypted with.
+    pub fn params(&self) -> &'static Parameters {
+        self.evaluation_key.params
+    }
+
+    // Returns the modulo q of the messages.
+    fn modulo(&self) -> usize {
+        self.evaluation_key.params.modulo
+    }
+
+    /// Serializes the ciphertext, along with the identifier and the parameters of its key set. The
+    /// ciphertexts of the plaintext backend are not meant to be stored, and are rejected.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, CiphertextError> {
+        let ciphertext = self
+            .ciphertext
+            .as_lwe()
+            .ok_or(CiphertextError::PlaintextBackend)?;
+        let header = CiphertextHeader {
+            version: CIPHERTEXT_VERSION,
+            key_set_id: self.key_id(),
+            parameters: ParametersHeader::from(self.params()),
+        };
+        let mut bytes = CIPHERTEXT_MAGIC.to_vec();
+        bincode::serialize_into(&mut bytes, &(header, ciphertext)).unwrap();
+        Ok(bytes)
+    }
+
+    /// Reads the header of a serialized ciphertext, e.g. to find the key set needed to load it.
+    pub fn read_header(bytes: &[u8]) -> Result<CiphertextHeader, CiphertextError> {
+        let payload = bytes.strip_prefix(CIPHERTEXT_MAGIC).ok_or_else(|| {
+            CiphertextError::InvalidFormat("missing ciphertext header".to_string())
+        })?;
+        let header: CiphertextHeader = bincode::deserialize(payload)
+            .map_err(|e| CiphertextError::InvalidFormat(e.to_string()))?;
+        if header.version != CIPHERTEXT_VERSION {
+            return Err(CiphertextError::UnsupportedVersion(header.version));
+        }
+        Ok(header)
+    }
+
+    /// Deserializes a ciphertext, which must have been encrypted with the key set of the
+    /// evaluation key.
+    pub fn from_bytes(
+        bytes: &[u8],
+        evaluation_key: &Rc<HomomorphicKey>,
+    ) -> Result<Ciphertext, CiphertextError> {
+        let header = Ciphertext::read_header(bytes)?;
+        if evaluation_key.backend() != Backend::Lwe {
+            return Err(CiphertextError::PlaintextBackend);
+        }
+        if header.key_set_id != evaluation_key.id {
+            return Err(CiphertextError::KeySetMismatch {
+                expected: evaluation_key.id,
+                found: header.key_set_id,
+            });
+        }
+        if header.parameters != ParametersHeader::from(evaluation_key.params) {
+            return Err(CiphertextError::InvalidFormat(
+                "the parameters do not match the ones of the key set".to_string(),
+            ));
+        }
+        let (_, ciphertext): (CiphertextHeader, crypto_api::LWE) =
+            bincode::deserialize(&bytes[CIPHERTEXT_MAGIC.len()..])
+                .map_err(|e| CiphertextError::InvalidFormat(e.to_string()))?;
+        Ok(Ciphertext {
+            ciphertext: RawCiphertext::Lwe(ciphertext),
+            evaluation_key: evaluation_key.clone(),
+        })
+    }
+
+    // Returns the encoder of the fresh encryptions, which encodes the results of the bootstraps:
+    // the encoder of a ciphertext translated by the addition of a constant would shift them out
+    // of [0, q).
+    pub(super) fn fresh_encoder(&self) -> crypto_api::Encoder {
+        zqz::utils::new_encoder(self.params())
+    }
+
+    // Returns the ciphertext encoded like a fresh encryption, by bootstrapping it (modulo) if it
+    // was translated by the addition of a constant, so that its message lies in [0, q).
+    pub(super) fn canonical(&self) -> Cow<'_, Ciphertext> {
+        let encoder = self.fresh_encoder();
+        if self.ciphertext.encoder() == &encoder {
+            return Cow::Borrowed(self);
+        }
+        let res = bs_ks(
+            &self.ciphertext,
+            &self.evaluation_key,
+            |x| zqz::utils::round_modulo(x, self.modulo()),
+            &encoder,
+        );
+        Cow::Owned(Ciphertext {
+            ciphertext: res,
+            evaluation_key: self.evaluation_key.clone(),
+        })
+    }
+
+    // Panics if the two ciphertexts were not encrypted with the same key set, since operating on
+    // them would produce garbage.
+    pub(super) fn check_key(&self, other: &Ciphertext) {
+        assert!(
+            self.key_id() == other.key_id(),
+            "the ciphertexts were encrypted with different key sets ({:016x} and {:016x})",
+            self.key_id(),
+            other.key_id()
+        );
+    }
+
+    /// Evaluates the polynomial with coefficients `coefs` (constant term first) on the encrypted
+    /// message, using a single bootstrap.
+    ///
+    /// For instance `ct.eval_poly(&[1, 2, 3])` computes `3x^2 + 2x + 1 mod q`.
+    pub fn eval_poly(&self, coefs: &[usize]) -> Ciphertext {
+        let coefs = coefs.to_vec();
+        let q = self.modulo();
+        self.eval(move |x| zqz::utils::poly_modulo(&coefs, x as usize, q) as f64)
+    }
+
+    /// Evaluates the sum `p_1(x_1) + ... + p_n(x_n)` of univariate polynomials over several
+    /// ciphertexts.
+    ///
+    /// Each polynomial costs one bootstrap, and the partial results are added using the padding
+    /// of the ciphertexts, so that a modulo bootstrap is only issued once the padding is spent.
+    pub fn eval_poly_sum(terms: &[(&Ciphertext, &[usize])]) -> Ciphertext {
+        assert!(!terms.is_empty(), "eval_poly_sum needs at least one term");
+        terms.iter().for_each(|(ct, _)| terms[0].0.check_key(ct));
+        let evaluation_key = terms[0].0.evaluation_key.clone();
+        let q = evaluation_key.params.modulo;
+        let encoder = zqz::utils::new_encoder(evaluation_key.params);
+
+        let evaluated: Vec<RawCiphertext> = terms
+            .iter()
+            .map(|(ct, coefs)| {
+                bs_ks(
+                    &ct.ciphertext,
+                    &evaluation_key,
+                    |x| {
+                        let x = zqz::utils::round_modulo(x, q) as usize;
+                        zqz::utils::poly_modulo(coefs, x, q) as f64
+                    },
+                    &encoder,
+                )
+            })
+            .collect();
+
+        Ciphertext {
+            ciphertext: sum_with_padding(evaluated, &evaluation_key),
+            evaluation_key,
+        }
+    }
+
+    /// Applies a lookup table to the encrypted message, using a single bootstrap.
+    pub fn apply(&self, lut: &LookupTable) -> Ciphertext {
+        let q = self.modulo();
+        assert_eq!(
+            lut.modulo(),
+            q,
+            "the lookup table does not match the modulo of the ciphertext"
+        );
+        let res = bs_ks(
+            &self.ciphertext,
+            &self.evaluation_key,
+            |x| lut.get(zqz::utils::round_modulo(x, q) as usize) as f64,
+            &self.fresh_encoder(),
+        );
+
+        Ciphertext {
+            ciphertext: res,
+            evaluation_key: self.evaluation_key.clone(),
+        }
+    }
+
+    /// Applies a lookup table to the sum of two encrypted messages with a single bootstrap, which
+    /// also reduces the sum modulo q, instead of the two bootstraps of `&a + &b` followed by
+    /// `Ciphertext::apply`.
+    pub fn add_apply(&self, other: &Ciphertext, lut: &LookupTable) -> Ciphertext {
+        self.check_key(other);
+        let sum = self.ciphertext.add_with_padding_exact(&other.ciphertext);
+        self.apply_padded(&sum, lut)
+    }
+
+    /// Applies a lookup table to the difference of two encrypted messages with a single
+    /// bootstrap, see `Ciphertext::add_apply`.
+    pub fn sub_apply(&self, other: &Ciphertext, lut: &LookupTable) -> Ciphertext {
+        self.check_key(other);
+        let sub = self.ciphertext.sub_with_padding_exact(&other.ciphertext);
+        self.apply_padded(&sub, lut)
+    }
+
+    // Bootstraps the result of an operation using the padding with the lookup table composed with
+    // the modulo.
+    fn apply_padded(&self, ciphertext: &RawCiphertext, lut: &LookupTable) -> Ciphertext {
+        let q = self.modulo();
+        assert_eq!(
+            lut.modulo(),
+            q,
+            "the lookup table does not match the modulo of the ciphertext"
+        );
+        let res = bs_ks(
+            ciphertext,
+            &self.evaluation_key,
+            |x| lut.get(zqz::utils::round_modulo(x, q) as usize) as f64,
+            &self.fresh_encoder(),
+        );
+
+        Ciphertext {
+            ciphertext: res,
+            evaluation_key: self.evaluation_key.clone(),
+        }
+    }
+
+    /// Applies several lookup tables to the same encrypted message, with one bootstrap per
+    /// distinct table.
+    ///
+    /// The concrete API builds the accumulator inside the bootstrap, so several tables cannot be
+    /// packed in a single blind rotation: the tables are applied with sequential bootstraps, and
+    /// only identical tables share the same bootstrap.
+    pub fn apply_many(&self, luts: &[LookupTable]) -> Vec<Ciphertext> {
+        let mut computed: Vec<(&LookupTable, Ciphertext)> = Vec::with_capacity(luts.len());
+        luts.iter()
+            .map(|lut| match computed.iter().find(|(other, _)| *other == lut) {
+                Some((_, ct)) => ct.clone(),
+                None => {
+                    let ct = self.apply(lut);
+                    computed.push((lut, ct.clone()));
+                    ct
+                }
+            })
+            .collect()
+    }
+
+    /// Evaluates several functions on the same encrypted message, see `Ciphertext::apply_many`.
+    pub fn eval_many(&self, fs: &[&dyn Fn(f64) -> f64]) -> Vec<Ciphertext> {
+        let q = self.modulo();
+        let luts: Vec<LookupTable> = fs.iter().map(|f| LookupTable::from_f64_fn(q, f)).collect();
+        self.apply_many(&luts)
+    }
+
+    /// Evaluates a real function on the encrypted message with a single bootstrap, its result
+    /// being rounded modulo q.
     pub fn eval<F: Fn(f64) -> f64>(&self, f: F) -> Ciphertext {
         // function and modulo
+        let q = self.modulo();
         let res = bs_ks(
             &self.ciphertext,
-            &self.evaluation_key.bootstrapping,
-            |x| zqz::utils::round_modulo(f(zqz::utils::round_modulo(x))),
-            &self.ciphertext.encoder,
-            &self.evaluation_key.keyswitching,
+            &self.evaluation_key,
+            |x| zqz::utils::round_modulo(f(zqz::utils::round_modulo(x, q)), q),
+            &self.fresh_encoder(),
         );
 
         Ciphertext {
@@ -50,6 +413,45 @@ impl Ciphertext {
             evaluation_key: self.evaluation_key.clone(),
         }
     }
+
+    /// Converts the encrypted message into a ciphertext of the target key set of the conversion
+    /// key, which may have been generated with other parameters.
+    ///
+    /// The ciphertext is keyswitched to the lwe secret key of the target key set, and then
+    /// bootstrapped with the target bootstrapping key, which encodes the message in the target
+    /// Z/qZ: a message of Z/8Z keeps its value in Z/16Z (range extension), while a message of Z/16Z
+    /// is reduced modulo 8 (modular reduction).
+    ///
+    /// An error is returned if the ciphertext does not belong to the source key set of the
+    /// conversion key.
+    pub fn convert(&self, key: &ConversionKey) -> Result<Ciphertext, CiphertextError> {
+        if self.key_id() != key.source_id() {
+            return Err(CiphertextError::KeySetMismatch {
+                expected: key.source_id(),
+                found: self.key_id(),
+            });
+        }
+        let q = self.modulo();
+        let evaluation_key = key.target.clone();
+        let target_q = evaluation_key.params.modulo;
+        let encoder = zqz::utils::new_encoder(evaluation_key.params);
+
+        // keyswitch
+        let switched = self.ciphertext.keyswitch(&key.keyswitching);
+
+        // range extension or modulo
+        let res = bs_ks(
+            &switched,
+            &evaluation_key,
+            |x| (zqz::utils::round_modulo(x, q) as usize % target_q) as f64,
+            &encoder,
+        );
+
+        Ok(Ciphertext {
+            ciphertext: res,
+            evaluation_key,
+        })
+    }
 }
 
 // Adds two ciphertexts using the `+` operator.
@@ -57,19 +459,17 @@ impl Add<&Ciphertext> for &Ciphertext {
     type Output = Ciphertext;
 
     fn add(self, other: &Ciphertext) -> Self::Output {
+        self.check_key(other);
+
         // addition
-        let sum = self
-            .ciphertext
-            .add_with_padding_exact(&other.ciphertext)
-            .unwrap();
+        let sum = self.ciphertext.add_with_padding_exact(&other.ciphertext);
 
         // modulo
         let res = bs_ks(
             &sum,
-            &self.evaluation_key.bootstrapping,
-            |x| zqz::utils::round_modulo(x),
-            &self.ciphertext.encoder,
-            &self.evaluation_key.keyswitching,
+            &self.evaluation_key,
+            |x| zqz::utils::round_modulo(x, self.modulo()),
+            &self.fresh_encoder(),
         );
 
         Ciphertext {
@@ -84,10 +484,7 @@ impl Add<usize> for &Ciphertext {
     type Output = Ciphertext;
 
     fn add(self, other: usize) -> Self::Output {
-        let res: crypto_api::LWE = self
-            .ciphertext
-            .add_constant_dynamic_encoder(other as f64)
-            .unwrap();
+        let res: RawCiphertext = self.ciphertext.add_constant_dynamic_encoder(other as f64);
 
         Ciphertext {
             ciphertext: res,
@@ -117,19 +514,17 @@ impl Sub<&Ciphertext> for &Ciphertext {
     type Output = Ciphertext;
 
     fn sub(self, other: &Ciphertext) -> Self::Output {
+        self.check_key(other);
+
         // subtraction
-        let sub = self
-            .ciphertext
-            .sub_with_padding_exact(&other.ciphertext)
-            .unwrap();
+        let sub = self.ciphertext.sub_with_padding_exact(&other.ciphertext);
 
         // modulo
         let res = bs_ks(
             &sub,
-            &self.evaluation_key.bootstrapping,
-            |x| zqz::utils::round_modulo(x),
-            &self.ciphertext.encoder,
-            &self.evaluation_key.keyswitching,
+            &self.evaluation_key,
+            |x| zqz::utils::round_modulo(x, self.modulo()),
+            &self.fresh_encoder(),
         );
 
         Ciphertext {
@@ -144,10 +539,9 @@ impl Sub<usize> for &Ciphertext {
     type Output = Ciphertext;
 
     fn sub(self, other: usize) -> Self::Output {
-        let res: crypto_api::LWE = self
+        let res: RawCiphertext = self
             .ciphertext
-            .add_constant_dynamic_encoder(-(other as f64))
-            .unwrap();
+            .add_constant_dynamic_encoder(-(other as f64));
 
         Ciphertext {
             ciphertext: res,
@@ -177,46 +571,39 @@ impl Mul<&Ciphertext> for &Ciphertext {
     type Output = Ciphertext;
 
     fn mul(self, other: &Ciphertext) -> Self::Output {
+        self.check_key(other);
+
         // addition
-        let posi = self
-            .ciphertext
-            .add_with_padding_exact(&other.ciphertext)
-            .unwrap();
+        let posi = self.ciphertext.add_with_padding_exact(&other.ciphertext);
 
         // subtraction
-        let nega = self
-            .ciphertext
-            .sub_with_padding_exact(&other.ciphertext)
-            .unwrap();
+        let nega = self.ciphertext.sub_with_padding_exact(&other.ciphertext);
 
         // modulo
         let mut res_posi = bs_ks(
             &posi,
-            &self.evaluation_key.bootstrapping,
-            |x| zqz::utils::floor_modulo(x * x / 4.),
-            &self.ciphertext.encoder,
-            &self.evaluation_key.keyswitching,
+            &self.evaluation_key,
+            |x| zqz::utils::floor_modulo(x * x / 4., self.modulo()),
+            &self.fresh_encoder(),
         );
 
         // modulo
         let res_nega = bs_ks(
             &nega,
-            &self.evaluation_key.bootstrapping,
-            |x| zqz::utils::floor_modulo(x * x / 4.),
-            &self.ciphertext.encoder,
-            &self.evaluation_key.keyswitching,
+            &self.evaluation_key,
+            |x| zqz::utils::floor_modulo(x * x / 4., self.modulo()),
+            &self.fresh_encoder(),
         );
 
         // subtraction
-        res_posi.sub_with_padding_exact_inplace(&res_nega).unwrap();
+        res_posi.sub_with_padding_exact_inplace(&res_nega);
 
         // modulo
         let res = bs_ks(
             &res_posi,
-            &self.evaluation_key.bootstrapping,
-            |x| zqz::utils::round_modulo(x),
-            &self.ciphertext.encoder,
-            &self.evaluation_key.keyswitching,
+            &self.evaluation_key,
+            |x| zqz::utils::round_modulo(x, self.modulo()),
+            &self.fresh_encoder(),
         );
 
         Ciphertext {
@@ -233,10 +620,9 @@ impl Mul<usize> for &Ciphertext {
     fn mul(self, other: usize) -> Self::Output {
         let res = bs_ks(
             &self.ciphertext,
-            &self.evaluation_key.bootstrapping,
-            |x| zqz::utils::round_modulo(x * (other as f64)),
-            &self.ciphertext.encoder,
-            &self.evaluation_key.keyswitching,
+            &self.evaluation_key,
+            |x| zqz::utils::round_modulo(x * (other as f64), self.modulo()),
+            &self.fresh_encoder(),
         );
 
         Ciphertext {
@@ -268,10 +654,14 @@ impl Max<usize> for &Ciphertext {
     fn max(self, rhs: usize) -> Self::Output {
         let res = bs_ks(
             &self.ciphertext,
-            &self.evaluation_key.bootstrapping,
-            |x| f64::max(x, (rhs % PARAMS.modulo) as f64),
-            &self.ciphertext.encoder,
-            &self.evaluation_key.keyswitching,
+            &self.evaluation_key,
+            |x| {
+                f64::max(
+                    zqz::utils::round_modulo(x, self.modulo()),
+                    (rhs % self.modulo()) as f64,
+                )
+            },
+            &self.fresh_encoder(),
         );
 
         Ciphertext {
@@ -293,31 +683,32 @@ impl Max<&Ciphertext> for usize {
 impl Max<&Ciphertext> for &Ciphertext {
     type Output = Ciphertext;
     fn max(self, rhs: &Ciphertext) -> Self::Output {
+        self.check_key(rhs);
+
+        // the relu compares the representatives of the messages, which must lie in [0, q)
+        let lhs = self.canonical();
+        let rhs = rhs.canonical();
+
         // subtraction
-        let sub = self
-            .ciphertext
-            .sub_with_padding_exact(&rhs.ciphertext)
-            .unwrap();
+        let sub = lhs.ciphertext.sub_with_padding_exact(&rhs.ciphertext);
 
         // relu
         let rel = bs_ks(
             &sub,
-            &self.evaluation_key.bootstrapping,
-            |x| zqz::utils::relu(x),
-            &self.ciphertext.encoder,
-            &self.evaluation_key.keyswitching,
+            &self.evaluation_key,
+            zqz::utils::relu,
+            &self.fresh_encoder(),
         );
 
         // addition
-        let add = rel.add_with_padding_exact(&rhs.ciphertext).unwrap();
+        let add = rel.add_with_padding_exact(&rhs.ciphertext);
 
         // modulo
         let res = bs_ks(
             &add,
-            &self.evaluation_key.bootstrapping,
-            |x| zqz::utils::round_modulo(x),
-            &self.ciphertext.encoder,
-            &self.evaluation_key.keyswitching,
+            &self.evaluation_key,
+            |x| zqz::utils::round_modulo(x, self.modulo()),
+            &self.fresh_encoder(),
         );
 
         Ciphertext {
@@ -0,0 +1,705 @@
+//! A module containing a computation graph over Z/qZ, built once and executed later.
+//!
+//! A `Circuit` declares its inputs and composes nodes mirroring the operators of `Ciphertext`.
+//! Nothing is computed while the circuit is built: it is executed as a whole on encrypted inputs
+//! with `Circuit::run`, or in the clear with `Circuit::run_clear` to get reference results.
+//!
+//! A circuit can also be serialized with `Circuit::to_bytes`, and shipped along with serialized
+//! ciphertexts to a server holding only the evaluation keys, which checks with
+//! `Circuit::check_params` that the circuit can be run with the parameters of its keys.
+use crate::zqz;
+use crate::PARAMS;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use zqz::ciphertext::Ciphertext;
+use zqz::lut::LookupTable;
+use zqz::max::max;
+use zqz::Parameters;
+
+/// The current version of the serialization format of the circuits.
+pub const CIRCUIT_VERSION: u32 = 1;
+
+const CIRCUIT_MAGIC: &[u8] = b"demo_z8z-circuit\n";
+
+/// The identifier of a node in a circuit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    /// Returns the position of the node in the circuit.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// A node of a circuit, whose operands are nodes declared before it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    /// The input of the given index.
+    Input(usize),
+    /// A constant of Z/qZ, in the clear.
+    Constant(usize),
+    Add(NodeId, NodeId),
+    Sub(NodeId, NodeId),
+    Mul(NodeId, NodeId),
+    Max(NodeId, NodeId),
+    /// The application of a lookup table, with a single bootstrap.
+    Apply(NodeId, LookupTable),
+    /// The application of a lookup table to a sum, see `Ciphertext::add_apply`.
+    AddApply(NodeId, NodeId, LookupTable),
+    /// The application of a lookup table to a difference, see `Ciphertext::sub_apply`.
+    SubApply(NodeId, NodeId, LookupTable),
+}
+
+impl Node {
+    /// Returns the operands of the node.
+    pub fn operands(&self) -> Vec<NodeId> {
+        match self {
+            Node::Input(_) | Node::Constant(_) => vec![],
+            Node::Add(a, b) | Node::Sub(a, b) | Node::Mul(a, b) | Node::Max(a, b) => vec![*a, *b],
+            Node::AddApply(a, b, _) | Node::SubApply(a, b, _) => vec![*a, *b],
+            Node::Apply(a, _) => vec![*a],
+        }
+    }
+
+    /// Computes the value of the node in the clear, from the values of its operands.
+    pub fn eval_clear(&self, operands: &[usize], q: usize) -> usize {
+        match self {
+            Node::Input(_) => panic!("the value of an input is not computed"),
+            Node::Constant(m) => *m,
+            Node::Add(..) => (operands[0] + operands[1]) % q,
+            Node::Sub(..) => (operands[0] + q - operands[1] % q) % q,
+            Node::Mul(..) => operands[0] * operands[1] % q,
+            Node::Max(..) => usize::max(operands[0], operands[1]),
+            Node::Apply(_, lut) => lut.get(operands[0]),
+            Node::AddApply(_, _, lut) => lut.get(operands[0] + operands[1]),
+            Node::SubApply(_, _, lut) => lut.get(operands[0] + q - operands[1] % q),
+        }
+    }
+}
+
+/// A computation graph over Z/qZ, see the module documentation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Circuit {
+    modulo: usize,
+    nb_inputs: usize,
+    nodes: Vec<Node>,
+    outputs: Vec<NodeId>,
+}
+
+/// The header of a serialized circuit, recording the parameters it requires.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CircuitHeader {
+    pub version: u32,
+    /// The modulo q of the messages.
+    pub modulo: usize,
+    /// The number of bits of padding the encrypted inputs need, see `Circuit::min_padding`.
+    pub min_padding: usize,
+    pub nb_inputs: usize,
+    pub nb_outputs: usize,
+}
+
+/// The errors raised when deserializing a circuit, or when it can not be run with a key set.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CircuitError {
+    /// The bytes are not a serialized circuit.
+    InvalidFormat(String),
+    /// The circuit was serialized with an unsupported version of the format.
+    UnsupportedVersion(u32),
+    /// A node of the circuit is invalid, e.g. it uses a node declared after it.
+    InvalidNode { index: usize, reason: String },
+    /// The circuit can not be run with the parameters of the key set.
+    UnsupportedParameters(String),
+}
+
+impl fmt::Display for CircuitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CircuitError::InvalidFormat(reason) => write!(f, "invalid circuit: {}", reason),
+            CircuitError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported circuit format version {} (expected {})",
+                version, CIRCUIT_VERSION
+            ),
+            CircuitError::InvalidNode { index, reason } => {
+                write!(f, "invalid circuit: node {}: {}", index, reason)
+            }
+            CircuitError::UnsupportedParameters(reason) => write!(
+                f,
+                "the circuit can not be run with these parameters: {}",
+                reason
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CircuitError {}
+
+// A serialized node, whose operands are given by their index and whose lookup tables are given
+// by their outputs, since they are only validated once the modulo of the circuit is known.
+#[derive(Serialize, Deserialize)]
+enum NodeRecord {
+    Input(usize),
+    Constant(usize),
+    Add(usize, usize),
+    Sub(usize, usize),
+    Mul(usize, usize),
+    Max(usize, usize),
+    Apply(usize, Vec<usize>),
+    AddApply(usize, usize, Vec<usize>),
+    SubApply(usize, usize, Vec<usize>),
+}
+
+// The serialized nodes and outputs of a circuit, following its header.
+#[derive(Serialize, Deserialize)]
+struct CircuitBody {
+    nodes: Vec<NodeRecord>,
+    outputs: Vec<usize>,
+}
+
+// The value of a node during an execution on encrypted inputs.
+#[derive(Clone)]
+enum Signal {
+    Clear(usize),
+    Encrypted(Ciphertext),
+}
+
+// The value of a node for the cost model: a constant computed in the clear, or a ciphertext whose
+// encoder is translated by a constant.
+#[derive(Clone, Copy)]
+enum Translation {
+    Clear(usize),
+    Encrypted(i64),
+}
+
+impl Circuit {
+    /// Creates an empty circuit over the Z/qZ of the current parameters.
+    pub fn new() -> Circuit {
+        Circuit::with_modulo(PARAMS.modulo)
+    }
+
+    /// Creates an empty circuit over Z/qZ for another modulo than the one of the current
+    /// parameters.
+    pub fn with_modulo(modulo: usize) -> Circuit {
+        Circuit {
+            modulo,
+            nb_inputs: 0,
+            nodes: Vec::new(),
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Returns the modulo q of the circuit.
+    pub fn modulo(&self) -> usize {
+        self.modulo
+    }
+
+    /// Returns the number of inputs of the circuit.
+    pub fn nb_inputs(&self) -> usize {
+        self.nb_inputs
+    }
+
+    /// Returns the nodes of the circuit, in the order they were declared.
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Returns the outputs of the circuit.
+    pub fn outputs(&self) -> &[NodeId] {
+        &self.outputs
+    }
+
+    // Appends a node, checking that its operands belong to the circuit.
+    fn push(&mut self, node: Node) -> NodeId {
+        for operand in node.operands().iter() {
+            assert!(
+                operand.0 < self.nodes.len(),
+                "the node {} does not belong to the circuit",
+                operand.0
+            );
+        }
+        self.nodes.push(node);
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Declares a new input, given at execution in the order of the declarations.
+    pub fn input(&mut self) -> NodeId {
+        self.nb_inputs += 1;
+        self.push(Node::Input(self.nb_inputs - 1))
+    }
+
+    /// Declares a constant of Z/qZ.
+    pub fn constant(&mut self, m: usize) -> NodeId {
+        self.push(Node::Constant(m % self.modulo))
+    }
+
+    pub fn add(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        self.push(Node::Add(a, b))
+    }
+
+    pub fn sub(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        self.push(Node::Sub(a, b))
+    }
+
+    pub fn mul(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        self.push(Node::Mul(a, b))
+    }
+
+    pub fn max(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        self.push(Node::Max(a, b))
+    }
+
+    /// Applies a lookup table, which must be defined over the Z/qZ of the circuit.
+    pub fn apply(&mut self, a: NodeId, lut: &LookupTable) -> NodeId {
+        assert_eq!(
+            lut.modulo(),
+            self.modulo,
+            "the lookup table does not match the modulo of the circuit"
+        );
+        self.push(Node::Apply(a, lut.clone()))
+    }
+
+    /// Applies a lookup table to the sum of two nodes.
+    pub fn add_apply(&mut self, a: NodeId, b: NodeId, lut: &LookupTable) -> NodeId {
+        assert_eq!(
+            lut.modulo(),
+            self.modulo,
+            "the lookup table does not match the modulo of the circuit"
+        );
+        self.push(Node::AddApply(a, b, lut.clone()))
+    }
+
+    /// Applies a lookup table to the difference of two nodes.
+    pub fn sub_apply(&mut self, a: NodeId, b: NodeId, lut: &LookupTable) -> NodeId {
+        assert_eq!(
+            lut.modulo(),
+            self.modulo,
+            "the lookup table does not match the modulo of the circuit"
+        );
+        self.push(Node::SubApply(a, b, lut.clone()))
+    }
+
+    /// Declares a node as an output of the circuit. An output must depend on an input, so that it
+    /// is encrypted.
+    pub fn output(&mut self, a: NodeId) {
+        assert!(
+            self.encrypted_nodes()[a.0],
+            "the output {} does not depend on any input",
+            a.0
+        );
+        self.outputs.push(a);
+    }
+
+    // Returns, for each node, whether its value depends on an input, i.e. is encrypted.
+    fn encrypted_nodes(&self) -> Vec<bool> {
+        let mut encrypted = Vec::with_capacity(self.nodes.len());
+        for node in self.nodes.iter() {
+            let res = match node {
+                Node::Input(_) => true,
+                node => node.operands().iter().any(|operand| encrypted[operand.0]),
+            };
+            encrypted.push(res);
+        }
+        encrypted
+    }
+
+    // Returns, for each node, whether its value is a ciphertext translated by the addition of a
+    // constant, which costs no bootstrap but must be bootstrapped before a max between two
+    // ciphertexts, see `Ciphertext::max`. The inputs are fresh encryptions.
+    fn translated_nodes(&self) -> Vec<bool> {
+        use Translation::{Clear, Encrypted};
+        let mut values: Vec<Translation> = Vec::with_capacity(self.nodes.len());
+        for node in self.nodes.iter() {
+            let operands: Vec<Translation> =
+                node.operands().iter().map(|id| values[id.0]).collect();
+            let clear: Option<Vec<usize>> = operands
+                .iter()
+                .map(|value| match value {
+                    Clear(m) => Some(*m),
+                    Encrypted(_) => None,
+                })
+                .collect();
+            let res = match (node, operands.as_slice(), clear) {
+                (Node::Input(_), _, _) => Encrypted(0),
+                (node, _, Some(operands)) => Clear(node.eval_clear(&operands, self.modulo)),
+                (Node::Add(..), [Encrypted(t), Clear(c)], _)
+                | (Node::Add(..), [Clear(c), Encrypted(t)], _) => Encrypted(t + *c as i64),
+                (Node::Sub(..), [Encrypted(t), Clear(c)], _) => Encrypted(t - *c as i64),
+                // c - x is computed as (q - 1) x + c
+                (Node::Sub(..), [Clear(c), Encrypted(_)], _) => Encrypted(*c as i64),
+                _ => Encrypted(0),
+            };
+            values.push(res);
+        }
+        values
+            .iter()
+            .map(|value| matches!(value, Encrypted(t) if *t != 0))
+            .collect()
+    }
+
+    /// Returns, for each node, whether an output depends on it.
+    pub fn live_nodes(&self) -> Vec<bool> {
+        let mut live = vec![false; self.nodes.len()];
+        self.outputs.iter().for_each(|id| live[id.0] = true);
+        for i in (0..self.nodes.len()).rev() {
+            if live[i] {
+                self.nodes[i]
+                    .operands()
+                    .iter()
+                    .for_each(|operand| live[operand.0] = true);
+            }
+        }
+        live
+    }
+
+    // Evaluates the nodes the outputs depend on in their order, `op` computing the value of a node
+    // from the values of its operands, and returns the values of the outputs.
+    fn evaluate<T: Clone, F: Fn(&Node, &[&T]) -> T>(&self, inputs: &[T], op: F) -> Vec<T> {
+        assert_eq!(
+            inputs.len(),
+            self.nb_inputs,
+            "the circuit takes {} input(s)",
+            self.nb_inputs
+        );
+        let live = self.live_nodes();
+        let mut values: Vec<Option<T>> = vec![None; self.nodes.len()];
+        for (i, node) in self.nodes.iter().enumerate() {
+            if !live[i] {
+                continue;
+            }
+            values[i] = Some(match node {
+                Node::Input(k) => inputs[*k].clone(),
+                _ => {
+                    let operands: Vec<&T> = node
+                        .operands()
+                        .iter()
+                        .map(|operand| values[operand.0].as_ref().unwrap())
+                        .collect();
+                    op(node, &operands)
+                }
+            });
+        }
+        self.outputs
+            .iter()
+            .map(|id| values[id.0].clone().unwrap())
+            .collect()
+    }
+
+    /// Executes the circuit in the clear, and returns the values of the outputs.
+    pub fn run_clear(&self, inputs: &[usize]) -> Vec<usize> {
+        let q = self.modulo;
+        let inputs: Vec<usize> = inputs.iter().map(|m| m % q).collect();
+        self.evaluate(&inputs, |node, operands| {
+            let operands: Vec<usize> = operands.iter().map(|m| **m).collect();
+            node.eval_clear(&operands, q)
+        })
+    }
+
+    /// Executes the circuit on encrypted inputs, and returns the encrypted outputs.
+    ///
+    /// The inputs must have been encrypted with the same key set, whose parameters are checked
+    /// with `Circuit::check_params` before anything is computed.
+    pub fn run(&self, inputs: &[Ciphertext]) -> Result<Vec<Ciphertext>, CircuitError> {
+        for ct in inputs.iter() {
+            self.check_params(ct.params())?;
+        }
+        let q = self.modulo;
+        let inputs: Vec<Signal> = inputs.iter().cloned().map(Signal::Encrypted).collect();
+        let outputs = self.evaluate(&inputs, |node, operands| match (node, operands) {
+            (Node::Constant(m), _) => Signal::Clear(*m),
+            (Node::Apply(_, lut), [a]) => apply(lut, a),
+            (Node::AddApply(_, _, lut), [Signal::Encrypted(a), Signal::Encrypted(b)]) => {
+                Signal::Encrypted(a.add_apply(b, lut))
+            }
+            (Node::SubApply(_, _, lut), [Signal::Encrypted(a), Signal::Encrypted(b)]) => {
+                Signal::Encrypted(a.sub_apply(b, lut))
+            }
+            (Node::AddApply(x, y, lut), [a, b]) => {
+                let sum = binary(&Node::Add(*x, *y), a, b, q);
+                apply(lut, &sum)
+            }
+            (Node::SubApply(x, y, lut), [a, b]) => {
+                let difference = binary(&Node::Sub(*x, *y), a, b, q);
+                apply(lut, &difference)
+            }
+            (_, [a, b]) => binary(node, a, b, q),
+            _ => unreachable!(),
+        });
+        Ok(outputs
+            .into_iter()
+            .map(|signal| match signal {
+                Signal::Encrypted(ct) => ct,
+                Signal::Clear(_) => unreachable!(),
+            })
+            .collect())
+    }
+
+    /// Returns the number of bits of padding the encrypted inputs need: one bit for the
+    /// bootstraps, and another one for the operations between two ciphertexts, which add or
+    /// subtract them using their padding.
+    pub fn min_padding(&self) -> usize {
+        let live = self.live_nodes();
+        let encrypted = self.encrypted_nodes();
+        let mut res = 0;
+        for (i, node) in self.nodes.iter().enumerate() {
+            if !live[i] || !encrypted[i] {
+                continue;
+            }
+            let operands = node.operands();
+            if operands.len() == 2 && operands.iter().all(|id| encrypted[id.0]) {
+                res = usize::max(res, 2);
+            } else if !operands.is_empty() {
+                res = usize::max(res, 1);
+            }
+        }
+        res
+    }
+
+    /// Checks that the circuit can be run on ciphertexts encrypted with the parameters.
+    pub fn check_params(&self, params: &Parameters) -> Result<(), CircuitError> {
+        if params.modulo != self.modulo {
+            return Err(CircuitError::UnsupportedParameters(format!(
+                "the circuit computes modulo {} instead of {}",
+                self.modulo, params.modulo
+            )));
+        }
+        if params.nb_bit_padding < self.min_padding() {
+            return Err(CircuitError::UnsupportedParameters(format!(
+                "the circuit needs {} bit(s) of padding, the parameters only have {}",
+                self.min_padding(),
+                params.nb_bit_padding
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns the number of bootstraps of an execution on fresh encrypted inputs, the operations
+    /// on constants only being computed in the clear.
+    pub fn bootstrap_cost(&self) -> usize {
+        let live = self.live_nodes();
+        let encrypted = self.encrypted_nodes();
+        let translated = self.translated_nodes();
+        let mut cost = 0;
+        for (i, node) in self.nodes.iter().enumerate() {
+            let operands: Vec<bool> = node.operands().iter().map(|id| encrypted[id.0]).collect();
+            let node_cost = match (node, operands.as_slice()) {
+                (_, [false]) | (_, [false, false]) => 0,
+                (Node::Input(_), _) | (Node::Constant(_), _) => 0,
+                (Node::Apply(..), _) => 1,
+                (Node::Add(..), [true, true]) | (Node::Sub(..), [true, true]) => 1,
+                (Node::Add(..), _) | (Node::Sub(..), [true, false]) => 0,
+                (Node::Sub(..), _) => 1,
+                (Node::Mul(..), [true, true]) => 3,
+                (Node::Max(a, b), [true, true]) => {
+                    2 + translated[a.0] as usize + translated[b.0] as usize
+                }
+                (Node::Mul(..), _) | (Node::Max(..), _) => 1,
+                (Node::AddApply(..), [true, true]) | (Node::SubApply(..), [true, true]) => 1,
+                (Node::AddApply(..), _) => 1,
+                (Node::SubApply(..), [true, false]) => 1,
+                (Node::SubApply(..), _) => 2,
+            };
+            if live[i] {
+                cost += node_cost;
+            }
+        }
+        cost
+    }
+}
+
+// Applies a lookup table to the value of a node.
+fn apply(lut: &LookupTable, a: &Signal) -> Signal {
+    match a {
+        Signal::Clear(a) => Signal::Clear(lut.get(*a)),
+        Signal::Encrypted(a) => Signal::Encrypted(a.apply(lut)),
+    }
+}
+
+// Computes a binary operation of a circuit, using the homomorphic operators as soon as one operand
+// is encrypted.
+fn binary(node: &Node, a: &Signal, b: &Signal, q: usize) -> Signal {
+    use Signal::{Clear, Encrypted};
+    match (a, b) {
+        (Clear(a), Clear(b)) => Clear(node.eval_clear(&[*a, *b], q)),
+        (Encrypted(a), Clear(b)) => Encrypted(match node {
+            Node::Add(..) => a + *b,
+            Node::Sub(..) => a - *b,
+            Node::Mul(..) => a * *b,
+            _ => max(a, *b),
+        }),
+        (Clear(a), Encrypted(b)) => Encrypted(match node {
+            Node::Add(..) => b + *a,
+            // a - b = (q - 1) * b + a
+            Node::Sub(..) => &(b * (q - 1)) + *a,
+            Node::Mul(..) => b * *a,
+            _ => max(*a, b),
+        }),
+        (Encrypted(a), Encrypted(b)) => Encrypted(match node {
+            Node::Add(..) => a + b,
+            Node::Sub(..) => a - b,
+            Node::Mul(..) => a * b,
+            _ => max(a, b),
+        }),
+    }
+}
+
+impl Circuit {
+    /// Returns the header of the serialized circuit.
+    pub fn header(&self) -> CircuitHeader {
+        CircuitHeader {
+            version: CIRCUIT_VERSION,
+            modulo: self.modulo,
+            min_padding: self.min_padding(),
+            nb_inputs: self.nb_inputs,
+            nb_outputs: self.outputs.len(),
+        }
+    }
+
+    /// Serializes the circuit: its inputs, nodes, constants, lookup tables and outputs, along
+    /// with the parameters it requires.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| match node {
+                Node::Input(k) => NodeRecord::Input(*k),
+                Node::Constant(m) => NodeRecord::Constant(*m),
+                Node::Add(a, b) => NodeRecord::Add(a.0, b.0),
+                Node::Sub(a, b) => NodeRecord::Sub(a.0, b.0),
+                Node::Mul(a, b) => NodeRecord::Mul(a.0, b.0),
+                Node::Max(a, b) => NodeRecord::Max(a.0, b.0),
+                Node::Apply(a, lut) => NodeRecord::Apply(a.0, lut.as_slice().to_vec()),
+                Node::AddApply(a, b, lut) => {
+                    NodeRecord::AddApply(a.0, b.0, lut.as_slice().to_vec())
+                }
+                Node::SubApply(a, b, lut) => {
+                    NodeRecord::SubApply(a.0, b.0, lut.as_slice().to_vec())
+                }
+            })
+            .collect();
+        let body = CircuitBody {
+            nodes,
+            outputs: self.outputs.iter().map(|id| id.0).collect(),
+        };
+        let mut bytes = CIRCUIT_MAGIC.to_vec();
+        bincode::serialize_into(&mut bytes, &(self.header(), body)).unwrap();
+        bytes
+    }
+
+    /// Reads the header of a serialized circuit, e.g. to check its requirements before loading
+    /// it.
+    pub fn read_header(bytes: &[u8]) -> Result<CircuitHeader, CircuitError> {
+        let payload = bytes
+            .strip_prefix(CIRCUIT_MAGIC)
+            .ok_or_else(|| CircuitError::InvalidFormat("missing circuit header".to_string()))?;
+        let header: CircuitHeader = bincode::deserialize(payload)
+            .map_err(|e| CircuitError::InvalidFormat(e.to_string()))?;
+        if header.version != CIRCUIT_VERSION {
+            return Err(CircuitError::UnsupportedVersion(header.version));
+        }
+        Ok(header)
+    }
+
+    /// Deserializes a circuit, and validates its nodes and its header.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Circuit, CircuitError> {
+        let header = Circuit::read_header(bytes)?;
+        let (_, body): (CircuitHeader, CircuitBody) =
+            bincode::deserialize(&bytes[CIRCUIT_MAGIC.len()..])
+                .map_err(|e| CircuitError::InvalidFormat(e.to_string()))?;
+        if header.modulo < 2 {
+            return Err(CircuitError::InvalidFormat(format!(
+                "invalid modulo {}",
+                header.modulo
+            )));
+        }
+
+        let mut circuit = Circuit::with_modulo(header.modulo);
+        for (index, record) in body.nodes.into_iter().enumerate() {
+            let invalid = |reason: String| CircuitError::InvalidNode { index, reason };
+            let operand = |i: usize| {
+                if i < index {
+                    Ok(NodeId(i))
+                } else {
+                    Err(invalid(format!("the operand {} is not declared before", i)))
+                }
+            };
+            let lut = |table: Vec<usize>| {
+                LookupTable::new(&table, header.modulo).map_err(|e| invalid(e.to_string()))
+            };
+            let node = match record {
+                NodeRecord::Input(k) if k == circuit.nb_inputs => {
+                    circuit.nb_inputs += 1;
+                    Node::Input(k)
+                }
+                NodeRecord::Input(k) => {
+                    return Err(invalid(format!("the input {} is not declared in order", k)))
+                }
+                NodeRecord::Constant(m) if m < header.modulo => Node::Constant(m),
+                NodeRecord::Constant(m) => {
+                    return Err(invalid(format!("the constant {} is not reduced", m)))
+                }
+                NodeRecord::Add(a, b) => Node::Add(operand(a)?, operand(b)?),
+                NodeRecord::Sub(a, b) => Node::Sub(operand(a)?, operand(b)?),
+                NodeRecord::Mul(a, b) => Node::Mul(operand(a)?, operand(b)?),
+                NodeRecord::Max(a, b) => Node::Max(operand(a)?, operand(b)?),
+                NodeRecord::Apply(a, table) => Node::Apply(operand(a)?, lut(table)?),
+                NodeRecord::AddApply(a, b, table) => {
+                    Node::AddApply(operand(a)?, operand(b)?, lut(table)?)
+                }
+                NodeRecord::SubApply(a, b, table) => {
+                    Node::SubApply(operand(a)?, operand(b)?, lut(table)?)
+                }
+            };
+            circuit.nodes.push(node);
+        }
+
+        let encrypted = circuit.encrypted_nodes();
+        for output in body.outputs.into_iter() {
+            if !encrypted.get(output).cloned().unwrap_or(false) {
+                return Err(CircuitError::InvalidFormat(format!(
+                    "the output {} is not an encrypted node",
+                    output
+                )));
+            }
+            circuit.outputs.push(NodeId(output));
+        }
+        if circuit.header() != header {
+            return Err(CircuitError::InvalidFormat(
+                "the header does not match the circuit".to_string(),
+            ));
+        }
+        Ok(circuit)
+    }
+}
+
+impl Default for Circuit {
+    fn default() -> Circuit {
+        Circuit::new()
+    }
+}
+
+impl fmt::Display for Circuit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, node) in self.nodes.iter().enumerate() {
+            write!(f, "%{} = ", i)?;
+            match node {
+                Node::Input(k) => write!(f, "input {}", k)?,
+                Node::Constant(m) => write!(f, "{}", m)?,
+                Node::Add(a, b) => write!(f, "%{} + %{}", a.0, b.0)?,
+                Node::Sub(a, b) => write!(f, "%{} - %{}", a.0, b.0)?,
+                Node::Mul(a, b) => write!(f, "%{} * %{}", a.0, b.0)?,
+                Node::Max(a, b) => write!(f, "max(%{}, %{})", a.0, b.0)?,
+                Node::Apply(a, lut) => write!(f, "{:?}(%{})", lut.as_slice(), a.0)?,
+                Node::AddApply(a, b, lut) => {
+                    write!(f, "{:?}(%{} + %{})", lut.as_slice(), a.0, b.0)?
+                }
+                Node::SubApply(a, b, lut) => {
+                    write!(f, "{:?}(%{} - %{})", lut.as_slice(), a.0, b.0)?
+                }
+            }
+            writeln!(f)?;
+        }
+        let outputs: Vec<String> = self.outputs.iter().map(|id| format!("%{}", id.0)).collect();
+        write!(f, "outputs: {}", outputs.join(", "))
+    }
+}
@@ -0,0 +1,207 @@
+//! A module estimating the failure probability of the operations with a parameter set.
+//!
+//! The decryption errors of TFHE are probabilistic: the noise of a bootstrapped ciphertext follows
+//! a centered distribution, and the message is decrypted wrongly when the noise exceeds half the
+//! distance between two messages. An `Estimate` runs an operation many times on random messages,
+//! counts the decryption errors, and records the noise of the decrypted phases, which is the
+//! difference between the phase and the expected message, in units of messages.
+//!
+//! Failures are rare with the parameter sets of the demo, so the counted ones are complemented by
+//! a Wilson confidence interval, which bounds the failure probability even when no failure was
+//! observed. The noise is only observed in the results: a failure happens when the noise at the
+//! input of a bootstrap exceeds its margin, which the results do not show, so the noise statistics
+//! are not turned into a failure probability.
+use crate::zqz;
+use std::fmt;
+use zqz::ciphertext::Ciphertext;
+use zqz::keys::EncryptKey;
+use zqz::max::max;
+
+/// The quantile of the standard normal distribution of the 95% confidence intervals.
+pub const Z_95: f64 = 1.959_963_984_540_054;
+
+/// An operation whose failure probability is estimated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// A bootstrap of a fresh ciphertext with the identity function.
+    Bootstrap,
+    /// The addition of two fresh ciphertexts.
+    Add,
+    /// The multiplication of two fresh ciphertexts.
+    Mul,
+    /// The max of two fresh ciphertexts.
+    Max,
+}
+
+impl Operation {
+    /// The operations, in the order of the reports.
+    pub const ALL: [Operation; 4] = [
+        Operation::Bootstrap,
+        Operation::Add,
+        Operation::Mul,
+        Operation::Max,
+    ];
+
+    /// Returns the name of the operation, as given on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Operation::Bootstrap => "bootstrap",
+            Operation::Add => "add",
+            Operation::Mul => "mul",
+            Operation::Max => "max",
+        }
+    }
+
+    /// Returns the operation with this name.
+    pub fn from_name(name: &str) -> Option<Operation> {
+        Operation::ALL.iter().copied().find(|op| op.name() == name)
+    }
+
+    // Computes the operation on the messages in the clear.
+    fn clear(self, a: usize, b: usize, modulo: usize) -> usize {
+        match self {
+            Operation::Bootstrap => a,
+            Operation::Add => (a + b) % modulo,
+            Operation::Mul => (a * b) % modulo,
+            Operation::Max => usize::max(a, b),
+        }
+    }
+
+    // Computes the operation on the encrypted messages.
+    fn encrypted(self, a: &Ciphertext, b: &Ciphertext) -> Ciphertext {
+        match self {
+            Operation::Bootstrap => a.eval(|x| x),
+            Operation::Add => a + b,
+            Operation::Mul => a * b,
+            Operation::Max => max(a, b),
+        }
+    }
+}
+
+/// The noise observed in the decrypted phases, in units of messages.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct NoiseStats {
+    pub mean: f64,
+    pub std_dev: f64,
+    /// The largest absolute value of the noise.
+    pub max: f64,
+}
+
+impl NoiseStats {
+    /// Computes the statistics of the noise samples.
+    pub fn new(samples: &[f64]) -> NoiseStats {
+        if samples.is_empty() {
+            return NoiseStats::default();
+        }
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        let variance = samples.iter().map(|e| (e - mean) * (e - mean)).sum::<f64>() / n;
+        NoiseStats {
+            mean,
+            std_dev: variance.sqrt(),
+            max: samples.iter().fold(0., |acc, e| f64::max(acc, e.abs())),
+        }
+    }
+}
+
+/// The estimated failure probability of an operation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    pub operation: Operation,
+    pub runs: usize,
+    /// The number of runs whose result was decrypted wrongly.
+    pub failures: usize,
+    pub noise: NoiseStats,
+}
+
+impl Estimate {
+    /// Runs the operation `runs` times on random messages encrypted with the key, and records the
+    /// decryption errors and the noise of the results.
+    pub fn new(sk: &EncryptKey, operation: Operation, runs: usize) -> Estimate {
+        let q = sk.params().modulo;
+        let mut failures = 0;
+        let mut samples = Vec::with_capacity(runs);
+        for _ in 0..runs {
+            let a = zqz::utils::random_id() as usize % q;
+            let b = zqz::utils::random_id() as usize % q;
+            let expected = operation.clear(a, b, q);
+            let res = operation.encrypted(&sk.encrypt(a), &sk.encrypt(b));
+            if sk.decrypt(&res) != expected {
+                failures += 1;
+            }
+            samples.push(centered_noise(sk.decrypt_phase(&res), expected, q));
+        }
+        Estimate {
+            operation,
+            runs,
+            failures,
+            noise: NoiseStats::new(&samples),
+        }
+    }
+
+    /// Returns the observed failure rate.
+    pub fn failure_rate(&self) -> f64 {
+        if self.runs == 0 {
+            0.
+        } else {
+            self.failures as f64 / self.runs as f64
+        }
+    }
+
+    /// Returns the 95% confidence interval of the failure probability.
+    pub fn confidence_interval(&self) -> (f64, f64) {
+        wilson_interval(self.failures, self.runs, Z_95)
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (low, high) = self.confidence_interval();
+        write!(
+            f,
+            "{:<9} {:>4}/{:<6} rate {:.2e}, 95% CI [{:.2e}, {:.2e}], noise mean {:+.4} std {:.4} max {:.4}",
+            self.operation.name(),
+            self.failures,
+            self.runs,
+            self.failure_rate(),
+            low,
+            high,
+            self.noise.mean,
+            self.noise.std_dev,
+            self.noise.max
+        )
+    }
+}
+
+/// Returns the Wilson score interval of a probability observed `successes` times out of `runs`,
+/// for the quantile `z` of the standard normal distribution. Unlike the normal approximation, the
+/// interval is not empty when nothing was observed.
+pub fn wilson_interval(successes: usize, runs: usize, z: f64) -> (f64, f64) {
+    if runs == 0 {
+        return (0., 1.);
+    }
+    let n = runs as f64;
+    let p = successes as f64 / n;
+    let z2 = z * z;
+    let center = (p + z2 / (2. * n)) / (1. + z2 / n);
+    let half_width = z / (1. + z2 / n) * (p * (1. - p) / n + z2 / (4. * n * n)).sqrt();
+    // the bounds are exact when nothing, or only successes, were observed
+    let low = if successes == 0 {
+        0.
+    } else {
+        center - half_width
+    };
+    let high = if successes == runs {
+        1.
+    } else {
+        center + half_width
+    };
+    (low, high)
+}
+
+// Returns the noise of a decrypted phase, i.e. its distance to the expected message modulo q,
+// between -q/2 and q/2.
+fn centered_noise(phase: f64, expected: usize, modulo: usize) -> f64 {
+    let q = modulo as f64;
+    (phase - expected as f64 + q / 2.).rem_euclid(q) - q / 2.
+}
@@ -0,0 +1,688 @@
+//! A module containing a small language describing computations over encrypted Z/qZ numbers.
+//!
+//! A program is a list of statements separated by `;`, each one being either a binding
+//! `let x = <expr>` or an expression. Expressions are made of integer constants, variables, the
+//! operators `+`, `-` and `*`, parentheses, and calls to the functions `max(a, b)`, `enc(m)` and
+//! `dec(c)`, the last two needing the secret key. For instance:
+//!
+//! ```text
+//! let a = enc(4); let b = enc(7); dec(max(a * b, 3))
+//! ```
+//!
+//! A univariate function is written as the table of its q outputs, which can be applied directly
+//! or bound to a name and called like a function:
+//!
+//! ```text
+//! let square = [0, 1, 4, 1, 0, 1, 4, 1]; square(x) + [1, 0, 0, 0, 0, 0, 0, 0](y)
+//! ```
+//!
+//! Applying a table to a table composes them. Without the secret key, a program is evaluated over
+//! encrypted inputs bound to variables, see `eval_program`, or compiled into a circuit taking them
+//! as inputs, see `compile`.
+use crate::zqz;
+use crate::PARAMS;
+use std::collections::HashMap;
+use std::fmt;
+use zqz::ciphertext::Ciphertext;
+use zqz::circuit::{Circuit, NodeId};
+use zqz::keys::EncryptKey;
+use zqz::lut::LookupTable;
+use zqz::max::max;
+
+/// The errors raised when parsing or evaluating a program.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprError {
+    /// The program is not well formed, the position being a byte offset in the source.
+    Parse { position: usize, message: String },
+    /// The program can not be evaluated, e.g. it uses an unknown variable.
+    Eval(String),
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExprError::Parse { position, message } => {
+                write!(f, "syntax error at column {}: {}", position + 1, message)
+            }
+            ExprError::Eval(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+/// A binary operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+}
+
+/// An expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Constant(usize),
+    Variable(String),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    /// A lookup table, given by its outputs.
+    Table(Vec<usize>),
+    /// The application of a lookup table to an argument.
+    Apply(Box<Expr>, Box<Expr>),
+}
+
+/// A statement.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Let(String, Expr),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(usize),
+    Ident(String),
+    Let,
+    Symbol(char),
+}
+
+// Splits the source into tokens, along with their positions.
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ExprError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c.is_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let word = &source[start..end];
+            let token = if c.is_ascii_digit() {
+                Token::Int(word.parse().map_err(|_| ExprError::Parse {
+                    position: start,
+                    message: format!("invalid integer `{}`", word),
+                })?)
+            } else if word == "let" {
+                Token::Let
+            } else {
+                Token::Ident(word.to_string())
+            };
+            tokens.push((start, token));
+        } else if "+-*(),;=[]".contains(c) {
+            tokens.push((start, Token::Symbol(c)));
+            chars.next();
+        } else {
+            return Err(ExprError::Parse {
+                position: start,
+                message: format!("unexpected character `{}`", c),
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+// A recursive descent parser over the tokens.
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.index += 1;
+        token
+    }
+
+    // Returns an error at the position of the next token.
+    fn error(&self, message: &str) -> ExprError {
+        ExprError::Parse {
+            position: self
+                .tokens
+                .get(self.index)
+                .map_or(self.end, |(position, _)| *position),
+            message: message.to_string(),
+        }
+    }
+
+    // Consumes the symbol if it is the next token.
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.index += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), ExprError> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", symbol)))
+        }
+    }
+
+    // statement := 'let' IDENT '=' expr | expr
+    fn statement(&mut self) -> Result<Statement, ExprError> {
+        if self.peek() != Some(&Token::Let) {
+            return Ok(Statement::Expr(self.expr()?));
+        }
+        self.next();
+        let name = match self.next() {
+            Some(Token::Ident(name)) => name,
+            _ => {
+                self.index -= 1;
+                return Err(self.error("expected a variable name"));
+            }
+        };
+        self.expect('=')?;
+        Ok(Statement::Let(name, self.expr()?))
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Expr, ExprError> {
+        let mut left = self.term()?;
+        loop {
+            let op = if self.eat('+') {
+                BinOp::Add
+            } else if self.eat('-') {
+                BinOp::Sub
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.term()?));
+        }
+    }
+
+    // term := factor ('*' factor)*
+    fn term(&mut self) -> Result<Expr, ExprError> {
+        let mut left = self.factor()?;
+        while self.eat('*') {
+            left = Expr::Binary(BinOp::Mul, Box::new(left), Box::new(self.factor()?));
+        }
+        Ok(left)
+    }
+
+    // factor := INT | IDENT | IDENT '(' (expr (',' expr)*)? ')' | '(' expr ')'
+    //         | '[' INT (',' INT)* ']' ('(' expr ')')?
+    fn factor(&mut self) -> Result<Expr, ExprError> {
+        match self.next() {
+            Some(Token::Int(n)) => Ok(Expr::Constant(n)),
+            Some(Token::Ident(name)) => {
+                if !self.eat('(') {
+                    return Ok(Expr::Variable(name));
+                }
+                let mut args = Vec::new();
+                if !self.eat(')') {
+                    loop {
+                        args.push(self.expr()?);
+                        if self.eat(')') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                Ok(Expr::Call(name, args))
+            }
+            Some(Token::Symbol('(')) => {
+                let expr = self.expr()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some(Token::Symbol('[')) => {
+                let mut table = Vec::new();
+                loop {
+                    match self.next() {
+                        Some(Token::Int(n)) => table.push(n),
+                        _ => {
+                            self.index -= 1;
+                            return Err(self.error("expected an integer"));
+                        }
+                    }
+                    if self.eat(']') {
+                        break;
+                    }
+                    self.expect(',')?;
+                }
+                let table = Expr::Table(table);
+                if !self.eat('(') {
+                    return Ok(table);
+                }
+                let arg = self.expr()?;
+                self.expect(')')?;
+                Ok(Expr::Apply(Box::new(table), Box::new(arg)))
+            }
+            _ => {
+                self.index -= 1;
+                Err(self.error("expected an expression"))
+            }
+        }
+    }
+}
+
+/// Parses a program made of statements separated by `;`.
+pub fn parse(source: &str) -> Result<Vec<Statement>, ExprError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        index: 0,
+        end: source.len(),
+    };
+    let mut statements = Vec::new();
+    loop {
+        while parser.eat(';') {}
+        if parser.peek().is_none() {
+            return Ok(statements);
+        }
+        statements.push(parser.statement()?);
+        if parser.peek().is_some() {
+            parser.expect(';')?;
+        }
+    }
+}
+
+/// A value: an integer of Z/qZ, in the clear or encrypted, or a lookup table.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Clear(usize),
+    Encrypted(Ciphertext),
+    Table(LookupTable),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Clear(m) => write!(f, "{}", m),
+            Value::Encrypted(_) => write!(f, "<encrypted>"),
+            Value::Table(lut) => write!(f, "{}", lut),
+        }
+    }
+}
+
+/// Evaluates a program over encrypted inputs, bound to the given variable names, without the
+/// secret key. Returns the value of the last statement.
+pub fn eval_program(
+    source: &str,
+    inputs: &[(&str, Ciphertext)],
+) -> Result<Option<Value>, ExprError> {
+    let modulo = inputs
+        .first()
+        .map_or(PARAMS.modulo, |(_, ct)| ct.params().modulo);
+    let mut env = Env::new(modulo);
+    for (name, ct) in inputs.iter() {
+        env.set(name, Value::Encrypted(ct.clone()));
+    }
+    env.run(source)
+}
+
+/// The environment a program is evaluated in, holding the variables and, optionally, the secret
+/// key.
+pub struct Env<'a> {
+    key: Option<&'a EncryptKey>,
+    modulo: usize,
+    variables: HashMap<String, Value>,
+}
+
+impl<'a> Env<'a> {
+    /// Creates an empty environment over Z/qZ, without secret key: the encrypted values must be
+    /// bound to variables beforehand.
+    pub fn new(modulo: usize) -> Env<'a> {
+        Env {
+            key: None,
+            modulo,
+            variables: HashMap::new(),
+        }
+    }
+
+    /// Creates an empty environment, in which values are encrypted and decrypted with the key.
+    pub fn with_key(key: &'a EncryptKey) -> Env<'a> {
+        Env {
+            key: Some(key),
+            modulo: key.params().modulo,
+            variables: HashMap::new(),
+        }
+    }
+
+    /// Binds a value to a variable.
+    pub fn set(&mut self, name: &str, value: Value) {
+        self.variables.insert(name.to_string(), value);
+    }
+
+    /// Returns the value bound to a variable.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.variables.get(name)
+    }
+
+    /// Runs the statements of a program, and returns the value of the last one.
+    pub fn run(&mut self, source: &str) -> Result<Option<Value>, ExprError> {
+        let mut res = None;
+        for statement in parse(source)?.iter() {
+            res = Some(self.execute(statement)?);
+        }
+        Ok(res)
+    }
+
+    /// Executes a statement, and returns its value, which is also the value bound by a `let`.
+    pub fn execute(&mut self, statement: &Statement) -> Result<Value, ExprError> {
+        match statement {
+            Statement::Let(name, expr) => {
+                let value = self.eval(expr)?;
+                self.set(name, value.clone());
+                Ok(value)
+            }
+            Statement::Expr(expr) => self.eval(expr),
+        }
+    }
+
+    /// Evaluates an expression.
+    pub fn eval(&self, expr: &Expr) -> Result<Value, ExprError> {
+        match expr {
+            Expr::Constant(n) => Ok(Value::Clear(n % self.modulo)),
+            Expr::Variable(name) => self
+                .get(name)
+                .cloned()
+                .ok_or_else(|| ExprError::Eval(format!("unknown variable `{}`", name))),
+            Expr::Binary(op, left, right) => self.binary(*op, self.eval(left)?, self.eval(right)?),
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<Vec<Value>, ExprError>>()?;
+                self.call(name, args)
+            }
+            Expr::Table(table) => LookupTable::new(table, self.modulo)
+                .map(Value::Table)
+                .map_err(|e| ExprError::Eval(e.to_string())),
+            Expr::Apply(table, arg) => match self.eval(table)? {
+                Value::Table(lut) => apply(&lut, self.eval(arg)?),
+                _ => Err(ExprError::Eval("only a table can be applied".to_string())),
+            },
+        }
+    }
+
+    // Applies a binary operator, using the homomorphic operators as soon as one operand is
+    // encrypted.
+    fn binary(&self, op: BinOp, left: Value, right: Value) -> Result<Value, ExprError> {
+        let q = self.modulo;
+        Ok(match (left, right) {
+            (Value::Clear(a), Value::Clear(b)) => Value::Clear(binary_clear(op, a, b, q)),
+            (Value::Encrypted(a), Value::Clear(b)) => Value::Encrypted(match op {
+                BinOp::Add => &a + b,
+                BinOp::Sub => &a - b,
+                BinOp::Mul => &a * b,
+            }),
+            (Value::Clear(a), Value::Encrypted(b)) => Value::Encrypted(match op {
+                BinOp::Add => &b + a,
+                // a - b = (q - 1) * b + a
+                BinOp::Sub => &(&b * (q - 1)) + a,
+                BinOp::Mul => &b * a,
+            }),
+            (Value::Encrypted(a), Value::Encrypted(b)) => Value::Encrypted(match op {
+                BinOp::Add => &a + &b,
+                BinOp::Sub => &a - &b,
+                BinOp::Mul => &a * &b,
+            }),
+            _ => {
+                return Err(ExprError::Eval(
+                    "a table can not be an operand of an operator".to_string(),
+                ))
+            }
+        })
+    }
+
+    // Calls a builtin function, or applies the lookup table bound to the name.
+    fn call(&self, name: &str, mut args: Vec<Value>) -> Result<Value, ExprError> {
+        let arity = match name {
+            "enc" | "dec" => 1,
+            "max" => 2,
+            _ => match self.get(name) {
+                Some(Value::Table(_)) => 1,
+                _ => return Err(ExprError::Eval(format!("unknown function `{}`", name))),
+            },
+        };
+        check_arity(name, arity, &args)?;
+        let first = args.remove(0);
+        match (name, first) {
+            ("enc", Value::Clear(m)) => Ok(Value::Encrypted(self.key()?.encrypt(m))),
+            ("enc", _) => Err(ExprError::Eval("`enc` expects a clear value".to_string())),
+            ("dec", Value::Encrypted(ct)) => Ok(Value::Clear(self.key()?.decrypt(&ct))),
+            ("dec", _) => Err(ExprError::Eval(
+                "`dec` expects an encrypted value".to_string(),
+            )),
+            ("max", first) => Ok(match (first, args.remove(0)) {
+                (Value::Clear(a), Value::Clear(b)) => Value::Clear(usize::max(a, b)),
+                (Value::Encrypted(a), Value::Clear(b)) => Value::Encrypted(max(&a, b)),
+                (Value::Clear(a), Value::Encrypted(b)) => Value::Encrypted(max(a, &b)),
+                (Value::Encrypted(a), Value::Encrypted(b)) => Value::Encrypted(max(&a, &b)),
+                _ => {
+                    return Err(ExprError::Eval(
+                        "`max` expects integer arguments".to_string(),
+                    ))
+                }
+            }),
+            (_, arg) => match self.get(name) {
+                Some(Value::Table(lut)) => apply(lut, arg),
+                _ => unreachable!(),
+            },
+        }
+    }
+
+    // Returns the secret key, needed to encrypt and decrypt.
+    fn key(&self) -> Result<&'a EncryptKey, ExprError> {
+        self.key
+            .ok_or_else(|| ExprError::Eval("`enc` and `dec` need the secret key".to_string()))
+    }
+}
+
+// Applies a lookup table with a single bootstrap to an encrypted value, or composes two tables.
+fn apply(lut: &LookupTable, arg: Value) -> Result<Value, ExprError> {
+    Ok(match arg {
+        Value::Clear(m) => Value::Clear(lut.get(m)),
+        Value::Encrypted(ct) => Value::Encrypted(ct.apply(lut)),
+        Value::Table(other) => Value::Table(lut.compose(&other)),
+    })
+}
+
+// Applies a binary operator to two clear values.
+fn binary_clear(op: BinOp, a: usize, b: usize, q: usize) -> usize {
+    match op {
+        BinOp::Add => (a + b) % q,
+        BinOp::Sub => (a + q - b) % q,
+        BinOp::Mul => a * b % q,
+    }
+}
+
+// Checks the number of arguments given to a function.
+fn check_arity<T>(name: &str, arity: usize, args: &[T]) -> Result<(), ExprError> {
+    if args.len() != arity {
+        return Err(ExprError::Eval(format!(
+            "`{}` takes {} argument(s), {} were given",
+            name,
+            arity,
+            args.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Compiles a program into a circuit over Z/qZ, whose inputs are the variables `inputs` in their
+/// order, and whose output is the value of the last statement.
+///
+/// The clear values are computed while compiling, so that the circuit only holds the operations
+/// on encrypted values, and `enc` and `dec` are rejected since the circuit is run without the
+/// secret key.
+pub fn compile(source: &str, inputs: &[&str], modulo: usize) -> Result<Circuit, ExprError> {
+    let mut compiler = Compiler {
+        circuit: Circuit::with_modulo(modulo),
+        variables: HashMap::new(),
+    };
+    for name in inputs.iter() {
+        let id = compiler.circuit.input();
+        compiler
+            .variables
+            .insert(name.to_string(), Symbol::Node(id));
+    }
+    let mut res = None;
+    for statement in parse(source)?.iter() {
+        res = Some(compiler.execute(statement)?);
+    }
+    match res {
+        Some(Symbol::Node(id)) => {
+            compiler.circuit.output(id);
+            Ok(compiler.circuit)
+        }
+        Some(_) => Err(ExprError::Eval(
+            "the result of the program does not depend on any input".to_string(),
+        )),
+        None => Err(ExprError::Eval("the program is empty".to_string())),
+    }
+}
+
+// A value of a program being compiled: an integer known at compilation, a node of the circuit
+// depending on the inputs, or a lookup table.
+#[derive(Clone)]
+enum Symbol {
+    Clear(usize),
+    Node(NodeId),
+    Table(LookupTable),
+}
+
+// The state of a compilation: the circuit built so far and the variables bound to its nodes.
+struct Compiler {
+    circuit: Circuit,
+    variables: HashMap<String, Symbol>,
+}
+
+impl Compiler {
+    fn execute(&mut self, statement: &Statement) -> Result<Symbol, ExprError> {
+        match statement {
+            Statement::Let(name, expr) => {
+                let symbol = self.eval(expr)?;
+                self.variables.insert(name.to_string(), symbol.clone());
+                Ok(symbol)
+            }
+            Statement::Expr(expr) => self.eval(expr),
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Symbol, ExprError> {
+        let q = self.circuit.modulo();
+        match expr {
+            Expr::Constant(n) => Ok(Symbol::Clear(n % q)),
+            Expr::Variable(name) => self
+                .variables
+                .get(name)
+                .cloned()
+                .ok_or_else(|| ExprError::Eval(format!("unknown variable `{}`", name))),
+            Expr::Binary(op, left, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                self.binary(*op, left, right)
+            }
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<Vec<Symbol>, ExprError>>()?;
+                self.call(name, args)
+            }
+            Expr::Table(table) => LookupTable::new(table, q)
+                .map(Symbol::Table)
+                .map_err(|e| ExprError::Eval(e.to_string())),
+            Expr::Apply(table, arg) => match self.eval(table)? {
+                Symbol::Table(lut) => {
+                    let arg = self.eval(arg)?;
+                    Ok(self.apply(&lut, arg))
+                }
+                _ => Err(ExprError::Eval("only a table can be applied".to_string())),
+            },
+        }
+    }
+
+    // Applies a binary operator, computing it in the clear if both operands are clear.
+    fn binary(&mut self, op: BinOp, left: Symbol, right: Symbol) -> Result<Symbol, ExprError> {
+        let (a, b) = match (left, right) {
+            (Symbol::Clear(a), Symbol::Clear(b)) => {
+                return Ok(Symbol::Clear(binary_clear(op, a, b, self.circuit.modulo())))
+            }
+            (Symbol::Table(_), _) | (_, Symbol::Table(_)) => {
+                return Err(ExprError::Eval(
+                    "a table can not be an operand of an operator".to_string(),
+                ))
+            }
+            (left, right) => (self.node(left), self.node(right)),
+        };
+        Ok(Symbol::Node(match op {
+            BinOp::Add => self.circuit.add(a, b),
+            BinOp::Sub => self.circuit.sub(a, b),
+            BinOp::Mul => self.circuit.mul(a, b),
+        }))
+    }
+
+    // Calls `max`, or applies the lookup table bound to the name.
+    fn call(&mut self, name: &str, mut args: Vec<Symbol>) -> Result<Symbol, ExprError> {
+        let arity = match name {
+            "enc" | "dec" => {
+                return Err(ExprError::Eval(format!(
+                    "`{}` can not be compiled, the circuit is run without the secret key",
+                    name
+                )))
+            }
+            "max" => 2,
+            _ => match self.variables.get(name) {
+                Some(Symbol::Table(_)) => 1,
+                _ => return Err(ExprError::Eval(format!("unknown function `{}`", name))),
+            },
+        };
+        check_arity(name, arity, &args)?;
+        let first = args.remove(0);
+        match (name, first) {
+            ("max", first) => Ok(match (first, args.remove(0)) {
+                (Symbol::Clear(a), Symbol::Clear(b)) => Symbol::Clear(usize::max(a, b)),
+                (Symbol::Table(_), _) | (_, Symbol::Table(_)) => {
+                    return Err(ExprError::Eval(
+                        "`max` expects integer arguments".to_string(),
+                    ))
+                }
+                (a, b) => {
+                    let (a, b) = (self.node(a), self.node(b));
+                    Symbol::Node(self.circuit.max(a, b))
+                }
+            }),
+            (_, arg) => match self.variables.get(name).cloned() {
+                Some(Symbol::Table(lut)) => Ok(self.apply(&lut, arg)),
+                _ => unreachable!(),
+            },
+        }
+    }
+
+    // Applies a lookup table to a value, or composes two tables.
+    fn apply(&mut self, lut: &LookupTable, arg: Symbol) -> Symbol {
+        match arg {
+            Symbol::Clear(m) => Symbol::Clear(lut.get(m)),
+            Symbol::Node(id) => Symbol::Node(self.circuit.apply(id, lut)),
+            Symbol::Table(other) => Symbol::Table(lut.compose(&other)),
+        }
+    }
+
+    // Returns the node of an integer, declaring a constant for a clear one.
+    fn node(&mut self, symbol: Symbol) -> NodeId {
+        match symbol {
+            Symbol::Clear(m) => self.circuit.constant(m),
+            Symbol::Node(id) => id,
+            Symbol::Table(_) => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,316 @@
+//! A module containing the versioned header written at the beginning of every key file.
+//!
+//! A key file starts with a single line `demo_z8z-key <json header>`, followed by the serialized
+//! key. The header records the version of the format, the parameters the key was generated with,
+//! the identifier of the key set the key belongs to, the encoding of the key, and the size and the
+//! checksum of the payload, so that truncated or corrupted files are detected.
+use crate::zqz;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use zqz::keys::KeyError;
+use zqz::passphrase::KeyEncryption;
+use zqz::Parameters;
+
+/// The current version of the key file format.
+pub const FORMAT_VERSION: u32 = 5;
+
+/// The oldest version of the key file format which can still be read. Version 2 files do not
+/// record the encoding of the key, which is then the text one.
+pub const MIN_FORMAT_VERSION: u32 = 2;
+
+/// The first version of the key file format whose text encoding is json. The text payloads of the
+/// previous versions are in the text format of concrete, which can only be read from a file, so
+/// they are not supported anymore.
+pub const MIN_TEXT_FORMAT_VERSION: u32 = 5;
+
+const MAGIC: &str = "demo_z8z-key";
+
+/// The kind of key stored in a key file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyKind {
+    Secret,
+    Bootstrapping,
+    Keyswitching,
+    Public,
+}
+
+/// The encoding of the key stored in a key file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum KeyEncoding {
+    /// A json format, readable but large.
+    #[default]
+    Text,
+    /// A compact binary format.
+    Binary,
+    /// The binary format compressed with deflate, only available with the `compression` feature.
+    CompressedBinary,
+}
+
+impl KeyEncoding {
+    /// The encodings, in the order of the usage of the command line.
+    pub const ALL: [KeyEncoding; 3] = [
+        KeyEncoding::Text,
+        KeyEncoding::Binary,
+        KeyEncoding::CompressedBinary,
+    ];
+
+    /// Returns the name of the encoding, as given on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyEncoding::Text => "text",
+            KeyEncoding::Binary => "binary",
+            KeyEncoding::CompressedBinary => "compressed",
+        }
+    }
+
+    /// Returns the encoding with this name.
+    pub fn from_name(name: &str) -> Option<KeyEncoding> {
+        KeyEncoding::ALL.iter().copied().find(|e| e.name() == name)
+    }
+}
+
+/// A serializable record of all the fields of a `Parameters`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParametersHeader {
+    pub nb_bit_precision: usize,
+    pub modulo: usize,
+    pub max: f64,
+    pub nb_bit_padding: usize,
+    pub bs_base_log: usize,
+    pub bs_level: usize,
+    pub ks_base_log: usize,
+    pub ks_level: usize,
+    pub rlwe_polynomial_size: usize,
+    pub rlwe_dimension: usize,
+    pub rlwe_log2_std_dev: i32,
+    pub lwe_dimension: usize,
+    pub lwe_log2_std_dev: i32,
+    pub with_ks: bool,
+}
+
+impl From<&Parameters> for ParametersHeader {
+    fn from(params: &Parameters) -> Self {
+        ParametersHeader {
+            nb_bit_precision: params.nb_bit_precision,
+            modulo: params.modulo,
+            max: params.max,
+            nb_bit_padding: params.nb_bit_padding,
+            bs_base_log: params.bs_base_log,
+            bs_level: params.bs_level,
+            ks_base_log: params.ks_base_log,
+            ks_level: params.ks_level,
+            rlwe_polynomial_size: params.rlwe_setting.polynomial_size,
+            rlwe_dimension: params.rlwe_setting.dimension,
+            rlwe_log2_std_dev: params.rlwe_setting.log2_std_dev,
+            lwe_dimension: params.lwe_setting.dimension,
+            lwe_log2_std_dev: params.lwe_setting.log2_std_dev,
+            with_ks: params.with_ks,
+        }
+    }
+}
+
+/// The header of a key file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyHeader {
+    pub version: u32,
+    pub kind: KeyKind,
+    pub parameters: ParametersHeader,
+    pub key_set_id: u64,
+    #[serde(default)]
+    pub encoding: KeyEncoding,
+    /// The encryption of the payload under a passphrase, if any.
+    #[serde(default)]
+    pub encryption: Option<KeyEncryption>,
+    pub payload_size: u64,
+    pub checksum: String,
+}
+
+impl KeyHeader {
+    /// Creates the header of a key of the given kind, for the current format version. The size
+    /// and the checksum of the payload are filled when the file is written.
+    pub fn new(
+        kind: KeyKind,
+        params: &Parameters,
+        key_set_id: u64,
+        encoding: KeyEncoding,
+    ) -> KeyHeader {
+        KeyHeader {
+            version: FORMAT_VERSION,
+            kind,
+            parameters: ParametersHeader::from(params),
+            key_set_id,
+            encoding,
+            encryption: None,
+            payload_size: 0,
+            checksum: String::new(),
+        }
+    }
+
+    /// Returns the data authenticated along with an encrypted payload, i.e. the fields of the
+    /// header which do not depend on the payload.
+    pub fn associated_data(&self) -> Vec<u8> {
+        serde_json::to_vec(&(
+            self.version,
+            self.kind,
+            &self.parameters,
+            self.key_set_id,
+            self.encoding,
+        ))
+        .unwrap()
+    }
+
+    // Checks that the header describes a key of the expected kind, generated with the expected
+    // parameters.
+    fn validate(&self, path: &str, kind: KeyKind, params: &Parameters) -> Result<(), KeyError> {
+        let min_version = match self.encoding {
+            KeyEncoding::Text => MIN_TEXT_FORMAT_VERSION,
+            _ => MIN_FORMAT_VERSION,
+        };
+        if self.version < min_version || self.version > FORMAT_VERSION {
+            return Err(KeyError::UnsupportedVersion {
+                path: path.to_string(),
+                version: self.version,
+            });
+        }
+        if self.kind != kind {
+            return Err(KeyError::WrongKind {
+                path: path.to_string(),
+                expected: kind,
+                found: self.kind,
+            });
+        }
+        let expected = ParametersHeader::from(params);
+        if self.parameters != expected {
+            return Err(KeyError::ParametersMismatch {
+                path: path.to_string(),
+                expected: Box::new(expected),
+                found: Box::new(self.parameters.clone()),
+            });
+        }
+        Ok(())
+    }
+}
+
+// Creates (or truncates) a file for a key of the given kind. The files containing a secret key are
+// only readable and writable by their owner.
+fn create_file(path: &str, kind: KeyKind) -> Result<fs::File, KeyError> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        if kind == KeyKind::Secret {
+            options.mode(0o600);
+            // the mode is only applied on creation
+            if let Ok(metadata) = fs::metadata(path) {
+                let mut permissions = metadata.permissions();
+                permissions.set_mode(0o600);
+                fs::set_permissions(path, permissions).map_err(|e| KeyError::io(path, e))?;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = kind;
+    options.open(path).map_err(|e| KeyError::io(path, e))
+}
+
+// Returns the hexadecimal sha256 of the payload.
+fn checksum(payload: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(payload))
+}
+
+/// Writes a key file made of the header followed by the payload.
+///
+/// The file is first written under a temporary name and then renamed, so that an interrupted save
+/// never leaves a partial file under the final name.
+pub fn write_key_file(path: &str, mut header: KeyHeader, payload: &[u8]) -> Result<(), KeyError> {
+    header.payload_size = payload.len() as u64;
+    header.checksum = checksum(payload);
+
+    let tmp = format!("{}.tmp", path);
+    let mut file = create_file(&tmp, header.kind)?;
+    writeln!(
+        file,
+        "{} {}",
+        MAGIC,
+        serde_json::to_string(&header).unwrap()
+    )
+    .and_then(|_| file.write_all(payload))
+    .and_then(|_| file.sync_all())
+    .map_err(|e| KeyError::io(&tmp, e))?;
+    fs::rename(&tmp, path).map_err(|e| KeyError::io(path, e))
+}
+
+/// Reads the header of a key file and checks that the size of the file matches the one recorded
+/// in the header, without reading the payload.
+pub fn read_header(path: &str) -> Result<KeyHeader, KeyError> {
+    let file = fs::File::open(path).map_err(|e| KeyError::io(path, e))?;
+    let file_size = file.metadata().map_err(|e| KeyError::io(path, e))?.len();
+    let mut reader = BufReader::new(file);
+    let (header, header_size) = parse_header(path, &mut reader)?;
+    if file_size != header_size + header.payload_size {
+        return Err(KeyError::Corrupted {
+            path: path.to_string(),
+            reason: format!(
+                "the payload is {} bytes long instead of {}",
+                file_size.saturating_sub(header_size),
+                header.payload_size
+            ),
+        });
+    }
+    Ok(header)
+}
+
+// Parses the first line of a key file, and returns the header along with the size of the line.
+fn parse_header<R: BufRead>(path: &str, reader: &mut R) -> Result<(KeyHeader, u64), KeyError> {
+    let mut line = String::new();
+    let size = reader
+        .read_line(&mut line)
+        .map_err(|e| KeyError::io(path, e))?;
+    let json = match line.trim_end().strip_prefix(MAGIC) {
+        Some(json) => json,
+        None => return Err(KeyError::MissingHeader(path.to_string())),
+    };
+    let header = serde_json::from_str(json).map_err(|e| KeyError::InvalidHeader {
+        path: path.to_string(),
+        reason: e.to_string(),
+    })?;
+    Ok((header, size as u64))
+}
+
+/// Reads a key file, validates its header against the expected kind and parameters, and checks
+/// the size and the checksum of the payload.
+pub fn read_key_file(
+    path: &str,
+    kind: KeyKind,
+    params: &Parameters,
+) -> Result<(KeyHeader, Vec<u8>), KeyError> {
+    let file = fs::File::open(path).map_err(|e| KeyError::io(path, e))?;
+    let mut reader = BufReader::new(file);
+    let (header, _) = parse_header(path, &mut reader)?;
+    header.validate(path, kind, params)?;
+
+    let mut payload = Vec::new();
+    reader
+        .read_to_end(&mut payload)
+        .map_err(|e| KeyError::io(path, e))?;
+    if payload.len() as u64 != header.payload_size {
+        return Err(KeyError::Corrupted {
+            path: path.to_string(),
+            reason: format!(
+                "the payload is {} bytes long instead of {}",
+                payload.len(),
+                header.payload_size
+            ),
+        });
+    }
+    if checksum(&payload) != header.checksum {
+        return Err(KeyError::Corrupted {
+            path: path.to_string(),
+            reason: "checksum mismatch".to_string(),
+        });
+    }
+    Ok((header, payload))
+}
@@ -2,64 +2,376 @@
 use crate::zqz;
 use crate::PARAMS;
 use concrete::crypto_api;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::io;
+use std::ops::Deref;
 use std::rc::Rc;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroize;
+use zqz::backend::{Backend, PlainCiphertext, RawCiphertext};
+use zqz::header::{KeyEncoding, KeyHeader, KeyKind, ParametersHeader};
+use zqz::passphrase::Passphrase;
+use zqz::Parameters;
 
 const SECRET_FILE: &str = "secret_key.json";
 const BOOTSTRAPPING_FILE: &str = "bootstrapping_key.txt";
 const KEYSWITCHING_FILE: &str = "keyswitching_key.txt";
+const PUBLIC_FILE: &str = "public_key.bin";
 
 /// A set of keys publicly available, allowing to perform bootstrap and keyswitch operations on
-/// ciphertext.
+/// ciphertext. The key set of the plaintext backend has no LWE keys.
 #[derive(Debug, PartialEq)]
 pub struct HomomorphicKey {
+    pub(super) lwe: Option<LweKeys>,
+    pub(super) id: u64,
+    pub(super) params: &'static Parameters,
+}
+
+/// The bootstrapping and keyswitching keys of the LWE backend.
+#[derive(Debug, PartialEq)]
+pub struct LweKeys {
     pub(super) bootstrapping: crypto_api::LWEBSK,
     pub(super) keyswitching: crypto_api::LWEKSK,
 }
 
+impl HomomorphicKey {
+    /// Returns the backend performing the operations on the ciphertexts of the key set.
+    pub fn backend(&self) -> Backend {
+        match self.lwe {
+            Some(_) => Backend::Lwe,
+            None => Backend::Plaintext,
+        }
+    }
+
+    /// Returns the identifier of the key set, which is carried by every ciphertext.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the parameters the key set was generated with.
+    pub fn params(&self) -> &'static Parameters {
+        self.params
+    }
+
+    // Loads the evaluation keys from files, checking that they belong to the key set `id`.
+    pub(super) fn load_from_files_with_params(
+        prefix: &str,
+        params: &'static Parameters,
+        id: u64,
+    ) -> Result<HomomorphicKey, KeyError> {
+        let bsk_path = format!("{}_{}", prefix, BOOTSTRAPPING_FILE);
+        let (header, bsk) = read_key(&bsk_path, KeyKind::Bootstrapping, params, None)?;
+        check_key_set(&bsk_path, id, &header)?;
+
+        let ksk_path = format!("{}_{}", prefix, KEYSWITCHING_FILE);
+        let (header, ksk) = read_key(&ksk_path, KeyKind::Keyswitching, params, None)?;
+        check_key_set(&ksk_path, id, &header)?;
+
+        Ok(HomomorphicKey {
+            lwe: Some(LweKeys {
+                bootstrapping: bsk,
+                keyswitching: ksk,
+            }),
+            id,
+            params,
+        })
+    }
+}
+
+/// The errors raised when saving or loading keys.
+#[derive(Debug)]
+pub enum KeyError {
+    /// An IO error occurred on the file.
+    Io { path: String, source: io::Error },
+    /// The file does not start with a key header, e.g. it was written by an older version.
+    MissingHeader(String),
+    /// The header of the file can not be parsed.
+    InvalidHeader { path: String, reason: String },
+    /// The file was written with an unsupported version of the format.
+    UnsupportedVersion { path: String, version: u32 },
+    /// The file does not contain the expected kind of key.
+    WrongKind {
+        path: String,
+        expected: KeyKind,
+        found: KeyKind,
+    },
+    /// The key was generated with other parameters.
+    ParametersMismatch {
+        path: String,
+        expected: Box<ParametersHeader>,
+        found: Box<ParametersHeader>,
+    },
+    /// The key files do not belong to the same key set.
+    KeySetMismatch {
+        path: String,
+        expected: u64,
+        found: u64,
+    },
+    /// The key is stored with an encoding which is not supported by this build.
+    UnsupportedEncoding { path: String, encoding: KeyEncoding },
+    /// The key is encrypted, and no passphrase was given.
+    PassphraseRequired(String),
+    /// The key can not be decrypted with the given passphrase.
+    WrongPassphrase(String),
+    /// The key can not be encrypted or decrypted.
+    Encryption { path: String, reason: String },
+    /// The file is truncated or corrupted.
+    Corrupted { path: String, reason: String },
+    /// The key itself can not be loaded.
+    InvalidKey { path: String, reason: String },
+    /// Only some of the files of the key set with this prefix exist, e.g. after an interrupted
+    /// save.
+    PartialKeySet(String),
+    /// The public key has too few encryptions of zero to be secure, see `PublicKey::min_size`.
+    InsecurePublicKey { size: usize, min_size: usize },
+    /// A message can not be encrypted with the public key.
+    PublicEncryption(String),
+    /// The operation needs the LWE keys, which the key sets of the plaintext backend do not have.
+    PlaintextBackend,
+    /// A conversion key needs the decomposition of a keyswitch, which neither parameter set has.
+    NoKeyswitch,
+}
+
+impl KeyError {
+    pub(super) fn io(path: &str, source: io::Error) -> KeyError {
+        KeyError::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyError::Io { path, source } => write!(f, "{}: {}", path, source),
+            KeyError::MissingHeader(path) => write!(
+                f,
+                "{}: missing key header, the file was not written by this version",
+                path
+            ),
+            KeyError::InvalidHeader { path, reason } => {
+                write!(f, "{}: invalid key header: {}", path, reason)
+            }
+            KeyError::UnsupportedVersion { path, version } => write!(
+                f,
+                "{}: unsupported key format version {} (expected {})",
+                path,
+                version,
+                zqz::header::FORMAT_VERSION
+            ),
+            KeyError::WrongKind {
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}: expected a {:?} key, found a {:?} key",
+                path, expected, found
+            ),
+            KeyError::ParametersMismatch {
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}: the key was generated with other parameters\n  expected: {:?}\n  found:    {:?}",
+                path, expected, found
+            ),
+            KeyError::KeySetMismatch {
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}: the key belongs to the key set {:016x} instead of {:016x}",
+                path, found, expected
+            ),
+            KeyError::UnsupportedEncoding { path, encoding } => write!(
+                f,
+                "{}: the {:?} encoding requires the `compression` feature",
+                path, encoding
+            ),
+            KeyError::PassphraseRequired(path) => {
+                write!(f, "{}: the key is encrypted, a passphrase is required", path)
+            }
+            KeyError::WrongPassphrase(path) => write!(
+                f,
+                "{}: wrong passphrase, or the encrypted key was tampered with",
+                path
+            ),
+            KeyError::Encryption { path, reason } => {
+                write!(f, "{}: encryption error: {}", path, reason)
+            }
+            KeyError::Corrupted { path, reason } => {
+                write!(f, "{}: corrupted key file: {}", path, reason)
+            }
+            KeyError::InvalidKey { path, reason } => {
+                write!(f, "{}: invalid key: {}", path, reason)
+            }
+            KeyError::PartialKeySet(prefix) => write!(
+                f,
+                "{}: incomplete key set, some of its key files are missing",
+                prefix
+            ),
+            KeyError::InsecurePublicKey { size, min_size } => write!(
+                f,
+                "a public key of {} encryptions of zero is not secure, at least {} are needed",
+                size, min_size
+            ),
+            KeyError::PublicEncryption(reason) => {
+                write!(f, "public key encryption error: {}", reason)
+            }
+            KeyError::PlaintextBackend => write!(
+                f,
+                "the key set belongs to the plaintext backend, which has no LWE keys"
+            ),
+            KeyError::NoKeyswitch => write!(
+                f,
+                "neither parameter set has a keyswitch, whose decomposition a conversion key needs"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for KeyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            KeyError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// The lwe secret key, which is zeroized when dropped, redacted when debug-printed, and compared
+/// in constant time.
+pub(super) struct SecretKey(pub(super) crypto_api::LWESecretKey);
+
+impl Deref for SecretKey {
+    type Target = crypto_api::LWESecretKey;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.0.val.zeroize();
+    }
+}
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretKey(<redacted>)")
+    }
+}
+
+impl PartialEq for SecretKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.dimension == other.0.dimension && bool::from(self.0.val.ct_eq(&other.0.val))
+    }
+}
+
 /// A secret key available only to the user side, allowing to encrypt ant decrypt data.
 #[derive(Debug, PartialEq)]
 pub struct EncryptKey {
-    pub(super) secret: crypto_api::LWESecretKey,
+    pub(super) secret: Option<SecretKey>,
     pub(super) evaluation: Rc<HomomorphicKey>,
 }
 
 impl EncryptKey {
     /// Generates a new encrypt key
     pub fn new() -> EncryptKey {
+        EncryptKey::with_params(&PARAMS)
+    }
+
+    /// Generates a new encrypt key with the given parameters, which may differ from the ones the
+    /// program was compiled with.
+    pub fn with_params(params: &'static Parameters) -> EncryptKey {
         // We generate the lwe secret key
-        let rlwe_sk: crypto_api::RLWESecretKey =
-            crypto_api::RLWESecretKey::new(&PARAMS.rlwe_setting);
-        let lwe_sk: crypto_api::LWESecretKey = if PARAMS.with_ks {
-            crypto_api::LWESecretKey::new(&PARAMS.lwe_setting)
+        let mut rlwe_sk: crypto_api::RLWESecretKey =
+            crypto_api::RLWESecretKey::new(&params.rlwe_setting);
+        let lwe_sk: crypto_api::LWESecretKey = if params.with_ks {
+            crypto_api::LWESecretKey::new(&params.lwe_setting)
         } else {
             rlwe_sk.to_lwe_secret_key()
         };
+        let key = EncryptKey::from_secret_keys(&rlwe_sk, lwe_sk, params, zqz::utils::random_id());
+        rlwe_sk.val.zeroize();
+        key
+    }
+
+    /// Generates an encrypt key whose secret keys are deterministically derived from the seed.
+    ///
+    /// **For tests only**: the secret keys are as predictable as the seed. Only the secret keys
+    /// are seeded: concrete 0.1 draws the masks and the noise of its encryptions from its own
+    /// generator, which can not be seeded, so neither the evaluation keys nor the ciphertexts can
+    /// be derived from the seed. Two evaluation keys generated from the same seed are not
+    /// bit-for-bit identical, but they are interchangeable, see `test_seeded_evaluation_keys`.
+    #[cfg(test)]
+    pub fn from_seed(seed: u64, params: &'static Parameters) -> EncryptKey {
+        let (mut rlwe_sk, lwe_sk) = seeded_secret_keys(seed, params);
+        let key = EncryptKey::from_secret_keys(&rlwe_sk, lwe_sk, params, seed);
+        rlwe_sk.val.zeroize();
+        key
+    }
+
+    // Generates the bootstrapping and keyswitching keys of the given secret keys.
+    fn from_secret_keys(
+        rlwe_sk: &crypto_api::RLWESecretKey,
+        lwe_sk: crypto_api::LWESecretKey,
+        params: &'static Parameters,
+        id: u64,
+    ) -> EncryptKey {
         // We generats the bootstrapping and keyswitching keys
         let bsk: crypto_api::LWEBSK =
-            crypto_api::LWEBSK::new(&lwe_sk, &rlwe_sk, PARAMS.bs_base_log, PARAMS.bs_level);
-        let ksk: crypto_api::LWEKSK = if PARAMS.with_ks {
+            crypto_api::LWEBSK::new(&lwe_sk, rlwe_sk, params.bs_base_log, params.bs_level);
+        let ksk: crypto_api::LWEKSK = if params.with_ks {
             crypto_api::LWEKSK::new(
                 &rlwe_sk.to_lwe_secret_key(),
                 &lwe_sk,
-                PARAMS.ks_base_log,
-                PARAMS.ks_level,
+                params.ks_base_log,
+                params.ks_level,
             )
         } else {
             crypto_api::LWEKSK::zero(
                 &rlwe_sk.to_lwe_secret_key(),
                 &lwe_sk,
-                PARAMS.ks_base_log,
-                PARAMS.ks_level,
+                params.ks_base_log,
+                params.ks_level,
             )
         };
         // We pack the homomorphic keys
         let hk = HomomorphicKey {
-            bootstrapping: bsk,
-            keyswitching: ksk,
+            lwe: Some(LweKeys {
+                bootstrapping: bsk,
+                keyswitching: ksk,
+            }),
+            id,
+            params,
         };
 
         EncryptKey {
-            secret: lwe_sk,
+            secret: Some(SecretKey(lwe_sk)),
+            evaluation: Rc::new(hk),
+        }
+    }
+
+    /// Generates a key set of the plaintext backend: the ciphertexts store their messages in the
+    /// clear, and the operations on them are computed instantly with the same results as the ones
+    /// of the LWE backend, as long as the latter decrypts correctly. **It provides no security**,
+    /// and is meant to test the logic of encrypted programs.
+    pub fn plaintext(params: &'static Parameters) -> EncryptKey {
+        let hk = HomomorphicKey {
+            lwe: None,
+            id: zqz::utils::random_id(),
+            params,
+        };
+        EncryptKey {
+            secret: None,
             evaluation: Rc::new(hk),
         }
     }
@@ -78,67 +390,192 @@ impl EncryptKey {
             crypto_api::LWEKSK::zero(&lwe_sk, &lwe_sk, PARAMS.ks_base_log, PARAMS.ks_level);
         // We pack the homomorphic keys
         let hk = HomomorphicKey {
-            bootstrapping: bsk,
-            keyswitching: ksk,
+            lwe: Some(LweKeys {
+                bootstrapping: bsk,
+                keyswitching: ksk,
+            }),
+            id: zqz::utils::random_id(),
+            params: &PARAMS,
         };
 
         EncryptKey {
-            secret: lwe_sk,
+            secret: Some(SecretKey(lwe_sk)),
             evaluation: Rc::new(hk),
         }
     }
 
-    /// Checks whether the keys with this prefix exist or not.
-    pub fn keys_exist(prefix: &str) -> bool {
-        use std::path::Path;
-        Path::new(format!("{}_{}", prefix, SECRET_FILE).as_str()).exists()
-            && Path::new(format!("{}_{}", prefix, BOOTSTRAPPING_FILE).as_str()).exists()
-            && Path::new(format!("{}_{}", prefix, KEYSWITCHING_FILE).as_str()).exists()
+    /// Checks which key files with this prefix exist. The files are not read: a corrupted or
+    /// mismatching key set is complete as well, and the error is reported when it is loaded, so
+    /// that it is never replaced by a new key set.
+    pub fn key_set_state(prefix: &str) -> KeySetState {
+        let nb_files = key_files(prefix)
+            .iter()
+            .filter(|path| std::path::Path::new(path).exists())
+            .count();
+        match nb_files {
+            0 => KeySetState::Missing,
+            3 => KeySetState::Complete,
+            _ => KeySetState::Partial,
+        }
+    }
+
+    /// Saves the encryption keys to files in the binary encoding, each one starting with a header
+    /// recording the parameters and the key set.
+    pub fn save_to_files(&self, prefix: &str) -> Result<(), KeyError> {
+        self.save_to_files_with_encoding(prefix, KeyEncoding::Binary)
+    }
+
+    /// Saves the encryption keys to files with the given encoding.
+    pub fn save_to_files_with_encoding(
+        &self,
+        prefix: &str,
+        encoding: KeyEncoding,
+    ) -> Result<(), KeyError> {
+        self.save_to_files_with_params(prefix, self.params(), encoding, None)
+    }
+
+    /// Saves the encryption keys to files in the binary encoding, the secret key being encrypted
+    /// under the passphrase. The evaluation keys are public and are not encrypted.
+    pub fn save_to_files_with_passphrase(
+        &self,
+        prefix: &str,
+        passphrase: &Passphrase,
+    ) -> Result<(), KeyError> {
+        self.save_to_files_with_params(prefix, self.params(), KeyEncoding::Binary, Some(passphrase))
+    }
+
+    /// Loads the encryption keys from files, checking that they were generated with the current
+    /// parameters and that they belong to the same key set. The encoding of each file is read
+    /// from its header.
+    pub fn load_from_files(prefix: &str) -> Result<EncryptKey, KeyError> {
+        EncryptKey::load_from_files_with_params(prefix, &PARAMS, None)
     }
 
-    /// Saves the encryption keys to files
-    pub fn save_to_files(&self, prefix: &str) {
-        self.secret
-            .save(format!("{}_{}", prefix, SECRET_FILE).as_str())
-            .unwrap();
-        self.evaluation
-            .bootstrapping
-            .save(format!("{}_{}", prefix, BOOTSTRAPPING_FILE).as_str());
-        self.evaluation
-            .keyswitching
-            .save(format!("{}_{}", prefix, KEYSWITCHING_FILE).as_str());
+    /// Loads the encryption keys from files, the secret key being encrypted under the passphrase.
+    pub fn load_from_files_with_passphrase(
+        prefix: &str,
+        passphrase: &Passphrase,
+    ) -> Result<EncryptKey, KeyError> {
+        EncryptKey::load_from_files_with_params(prefix, &PARAMS, Some(passphrase))
     }
 
-    /// Loads the encryption keys from files
-    pub fn load_from_files(prefix: &str) -> EncryptKey {
+    // Loads the encryption keys from files written without header by the previous versions, in
+    // the text format of concrete. Nothing is checked, so this is only used by the key store to
+    // migrate old keys, by saving them again with headers.
+    pub(super) fn load_from_legacy_files(
+        prefix: &str,
+        params: &'static Parameters,
+    ) -> Result<EncryptKey, KeyError> {
+        let secret_path = format!("{}_{}", prefix, SECRET_FILE);
         let secret_key =
-            crypto_api::LWESecretKey::load(format!("{}_{}", prefix, SECRET_FILE).as_str())
-                .expect("No Secret Key File");
+            crypto_api::LWESecretKey::load(&secret_path).map_err(|e| KeyError::InvalidKey {
+                path: secret_path.clone(),
+                reason: e.to_string(),
+            })?;
         let bsk = crypto_api::LWEBSK::load(format!("{}_{}", prefix, BOOTSTRAPPING_FILE).as_str());
         let ksk = crypto_api::LWEKSK::load(format!("{}_{}", prefix, KEYSWITCHING_FILE).as_str());
         let hk = HomomorphicKey {
-            bootstrapping: bsk,
-            keyswitching: ksk,
+            lwe: Some(LweKeys {
+                bootstrapping: bsk,
+                keyswitching: ksk,
+            }),
+            id: zqz::utils::random_id(),
+            params,
         };
-        EncryptKey {
-            secret: secret_key,
+        Ok(EncryptKey {
+            secret: Some(SecretKey(secret_key)),
             evaluation: Rc::new(hk),
+        })
+    }
+
+    // Saves the encryption keys to files, recording the given parameters in the headers, and
+    // encrypting the secret key if a passphrase is given.
+    pub(super) fn save_to_files_with_params(
+        &self,
+        prefix: &str,
+        params: &Parameters,
+        encoding: KeyEncoding,
+        passphrase: Option<&Passphrase>,
+    ) -> Result<(), KeyError> {
+        let (secret, keys) = self.lwe_keys()?;
+        let id = self.evaluation.id;
+        write_key(
+            format!("{}_{}", prefix, SECRET_FILE).as_str(),
+            KeyHeader::new(KeyKind::Secret, params, id, encoding),
+            &**secret,
+            passphrase,
+        )?;
+        write_key(
+            format!("{}_{}", prefix, BOOTSTRAPPING_FILE).as_str(),
+            KeyHeader::new(KeyKind::Bootstrapping, params, id, encoding),
+            &keys.bootstrapping,
+            None,
+        )?;
+        write_key(
+            format!("{}_{}", prefix, KEYSWITCHING_FILE).as_str(),
+            KeyHeader::new(KeyKind::Keyswitching, params, id, encoding),
+            &keys.keyswitching,
+            None,
+        )
+    }
+
+    // Loads the encryption keys from files, checking their headers against the given parameters,
+    // and decrypting the secret key with the passphrase if it is encrypted.
+    pub(super) fn load_from_files_with_params(
+        prefix: &str,
+        params: &'static Parameters,
+        passphrase: Option<&Passphrase>,
+    ) -> Result<EncryptKey, KeyError> {
+        let secret_path = format!("{}_{}", prefix, SECRET_FILE);
+        let (header, secret_key) = read_key(&secret_path, KeyKind::Secret, params, passphrase)?;
+        let id = header.key_set_id;
+        let hk = HomomorphicKey::load_from_files_with_params(prefix, params, id)?;
+        Ok(EncryptKey {
+            secret: Some(SecretKey(secret_key)),
+            evaluation: Rc::new(hk),
+        })
+    }
+
+    /// Returns the identifier of the key set, which is carried by every ciphertext.
+    pub fn key_id(&self) -> u64 {
+        self.evaluation.id
+    }
+
+    /// Returns the parameters the keys were generated with.
+    pub fn params(&self) -> &'static Parameters {
+        self.evaluation.params
+    }
+
+    /// Returns the backend performing the operations on the ciphertexts of the key set.
+    pub fn backend(&self) -> Backend {
+        self.evaluation.backend()
+    }
+
+    // Returns the lwe secret key and the evaluation keys of the LWE backend, which the key sets
+    // of the plaintext backend do not have.
+    pub(super) fn lwe_keys(&self) -> Result<(&SecretKey, &LweKeys), KeyError> {
+        match (&self.secret, &self.evaluation.lwe) {
+            (Some(secret), Some(keys)) => Ok((secret, keys)),
+            _ => Err(KeyError::PlaintextBackend),
         }
     }
 
+    /// Returns the evaluation keys, which are shared by all the ciphertexts of the key set.
+    pub fn evaluation_key(&self) -> &Rc<HomomorphicKey> {
+        &self.evaluation
+    }
+
     /// Encrypt the given message
     pub fn encrypt(&self, message: usize) -> zqz::ciphertext::Ciphertext {
-        let m = message % PARAMS.modulo;
-        let encoder: crypto_api::Encoder = crypto_api::Encoder::new_rounding_context(
-            0.,
-            PARAMS.max,
-            PARAMS.nb_bit_precision,
-            PARAMS.nb_bit_padding,
-        )
-        .unwrap();
+        let m = message % self.params().modulo;
+        let encoder: crypto_api::Encoder = zqz::utils::new_encoder(self.params());
 
-        let ct: crypto_api::LWE =
-            crypto_api::LWE::encode_encrypt(&self.secret, m as f64, &encoder).unwrap();
+        let ct = match self.secret {
+            Some(ref secret) => RawCiphertext::Lwe(
+                crypto_api::LWE::encode_encrypt(secret, m as f64, &encoder).unwrap(),
+            ),
+            None => RawCiphertext::Plaintext(PlainCiphertext::encode(m as f64, &encoder)),
+        };
         zqz::ciphertext::Ciphertext {
             ciphertext: ct,
             evaluation_key: self.evaluation.clone(),
@@ -147,7 +584,298 @@ impl EncryptKey {
 
     /// We decrypt the ciphertext
     pub fn decrypt(&self, ct: &zqz::ciphertext::Ciphertext) -> usize {
-        let dec: f64 = ct.ciphertext.decrypt_decode(&self.secret).unwrap();
-        zqz::utils::round_modulo(dec) as usize
+        assert!(
+            ct.key_id() == self.key_id(),
+            "the ciphertext was encrypted with another key set ({:016x} instead of {:016x})",
+            ct.key_id(),
+            self.key_id()
+        );
+        zqz::utils::round_modulo(self.decrypt_phase(ct), self.params().modulo) as usize
+    }
+
+    // Decrypts the ciphertext without rounding, which gives the message plus the noise.
+    pub(super) fn decrypt_phase(&self, ct: &zqz::ciphertext::Ciphertext) -> f64 {
+        match (&ct.ciphertext, &self.secret) {
+            (RawCiphertext::Lwe(ct), Some(secret)) => ct.decrypt_decode(secret).unwrap(),
+            (RawCiphertext::Plaintext(ct), None) => ct.decode(),
+            _ => panic!("the ciphertext belongs to another backend than the key set"),
+        }
+    }
+
+    /// Encrypts the given boolean as 0 or 1
+    pub fn encrypt_bool(&self, message: bool) -> zqz::boolean::BoolCiphertext {
+        zqz::boolean::BoolCiphertext(self.encrypt(message as usize))
+    }
+
+    /// Decrypts the given boolean ciphertext
+    pub fn decrypt_bool(&self, ct: &zqz::boolean::BoolCiphertext) -> bool {
+        self.decrypt(&ct.0) != 0
+    }
+}
+
+/// A key converting the ciphertexts of a key set into ciphertexts of another key set, possibly
+/// generated with other parameters, see `Ciphertext::convert`.
+///
+/// It is made of a keyswitching key from the lwe secret key of the source key set to the one of
+/// the target key set, and of the evaluation keys of the target key set. Both secret keys are
+/// needed to generate it, but it is then as public as the evaluation keys.
+#[derive(Debug, PartialEq)]
+pub struct ConversionKey {
+    pub(super) keyswitching: crypto_api::LWEKSK,
+    pub(super) source_id: u64,
+    pub(super) target: Rc<HomomorphicKey>,
+}
+
+impl ConversionKey {
+    /// Generates the key converting the ciphertexts of `source` into ciphertexts of `target`,
+    /// which must both belong to the LWE backend.
+    ///
+    /// The keyswitching key is decomposed like the one of the target parameters, or like the one
+    /// of the source parameters if the target ones have no keyswitch: one of them must have a
+    /// keyswitch.
+    pub fn new(source: &EncryptKey, target: &EncryptKey) -> Result<ConversionKey, KeyError> {
+        let (source_secret, _) = source.lwe_keys()?;
+        let (target_secret, _) = target.lwe_keys()?;
+        let params = [target.params(), source.params()]
+            .iter()
+            .copied()
+            .find(|params| params.with_ks)
+            .ok_or(KeyError::NoKeyswitch)?;
+        let ksk = crypto_api::LWEKSK::new(
+            source_secret,
+            target_secret,
+            params.ks_base_log,
+            params.ks_level,
+        );
+        Ok(ConversionKey {
+            keyswitching: ksk,
+            source_id: source.key_id(),
+            target: target.evaluation.clone(),
+        })
+    }
+
+    /// Returns the identifier of the key set the ciphertexts are converted from.
+    pub fn source_id(&self) -> u64 {
+        self.source_id
+    }
+
+    /// Returns the identifier of the key set the ciphertexts are converted to.
+    pub fn target_id(&self) -> u64 {
+        self.target.id
+    }
+
+    /// Returns the parameters of the key set the ciphertexts are converted to.
+    pub fn target_params(&self) -> &'static Parameters {
+        self.target.params
+    }
+}
+
+/// Derives the rlwe and lwe secret keys from the seed, for tests only.
+#[cfg(test)]
+pub(super) fn seeded_secret_keys(
+    seed: u64,
+    params: &Parameters,
+) -> (crypto_api::RLWESecretKey, crypto_api::LWESecretKey) {
+    use rand_core::RngCore;
+
+    // the binary secret keys are stored as packed bits, so any random words make a valid key
+    let mut rng = zqz::utils::seeded_rng(seed);
+    let mut rlwe_sk = crypto_api::RLWESecretKey::new(&params.rlwe_setting);
+    for val in rlwe_sk.val.iter_mut() {
+        *val = rng.next_u64() as _;
+    }
+    let lwe_sk = if params.with_ks {
+        let mut lwe_sk = crypto_api::LWESecretKey::new(&params.lwe_setting);
+        for val in lwe_sk.val.iter_mut() {
+            *val = rng.next_u64() as _;
+        }
+        lwe_sk
+    } else {
+        rlwe_sk.to_lwe_secret_key()
+    };
+    (rlwe_sk, lwe_sk)
+}
+
+// Writes a key file with the encoding given in the header. The key is serialized in memory, and
+// the payload is encrypted if a passphrase is given, so that the secret key is never written in
+// the clear.
+fn write_key<K: Serialize>(
+    path: &str,
+    mut header: KeyHeader,
+    key: &K,
+    passphrase: Option<&Passphrase>,
+) -> Result<(), KeyError> {
+    // the serialized buffers may contain the secret key, so they are zeroized once written
+    let mut payload = match header.encoding {
+        KeyEncoding::Text => serde_json::to_vec(key).unwrap(),
+        KeyEncoding::Binary => bincode::serialize(key).unwrap(),
+        KeyEncoding::CompressedBinary => {
+            let mut serialized = bincode::serialize(key).unwrap();
+            let compressed = compress(path, &serialized);
+            serialized.zeroize();
+            compressed?
+        }
+    };
+    if let Some(passphrase) = passphrase {
+        let encrypted =
+            zqz::passphrase::encrypt(path, passphrase, &payload, &header.associated_data());
+        payload.zeroize();
+        let (encryption, encrypted) = encrypted?;
+        header.encryption = Some(encryption);
+        payload = encrypted;
+    }
+    let res = zqz::header::write_key_file(path, header, &payload);
+    payload.zeroize();
+    res
+}
+
+// Reads a key file with the encoding given in its header, deserializing the key in memory. The
+// payload is decrypted with the passphrase if it is encrypted.
+fn read_key<K: DeserializeOwned>(
+    path: &str,
+    kind: KeyKind,
+    params: &Parameters,
+    passphrase: Option<&Passphrase>,
+) -> Result<(KeyHeader, K), KeyError> {
+    // the read buffers may contain the secret key, so they are zeroized once deserialized
+    let (header, mut payload) = zqz::header::read_key_file(path, kind, params)?;
+    if let Some(encryption) = &header.encryption {
+        let passphrase =
+            passphrase.ok_or_else(|| KeyError::PassphraseRequired(path.to_string()))?;
+        payload = zqz::passphrase::decrypt(
+            path,
+            passphrase,
+            encryption,
+            &payload,
+            &header.associated_data(),
+        )?;
+    }
+    let key = match header.encoding {
+        KeyEncoding::Text => Ok(serde_json::from_slice(&payload).map_err(|e| e.to_string())),
+        KeyEncoding::Binary => Ok(bincode::deserialize(&payload).map_err(|e| e.to_string())),
+        KeyEncoding::CompressedBinary => decompress(path, &payload).map(|mut decompressed| {
+            let key = bincode::deserialize(&decompressed).map_err(|e| e.to_string());
+            decompressed.zeroize();
+            key
+        }),
+    };
+    payload.zeroize();
+    let key = key?.map_err(|reason| KeyError::InvalidKey {
+        path: path.to_string(),
+        reason,
+    })?;
+    Ok((header, key))
+}
+
+#[cfg(feature = "compression")]
+fn compress(path: &str, payload: &[u8]) -> Result<Vec<u8>, KeyError> {
+    use std::io::Write;
+    let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder
+        .write_all(payload)
+        .and_then(|_| encoder.finish())
+        .map_err(|e| KeyError::io(path, e))
+}
+
+#[cfg(not(feature = "compression"))]
+fn compress(path: &str, _payload: &[u8]) -> Result<Vec<u8>, KeyError> {
+    Err(KeyError::UnsupportedEncoding {
+        path: path.to_string(),
+        encoding: KeyEncoding::CompressedBinary,
+    })
+}
+
+#[cfg(feature = "compression")]
+fn decompress(path: &str, payload: &[u8]) -> Result<Vec<u8>, KeyError> {
+    use std::io::Read;
+    let mut res = Vec::new();
+    flate2::read::DeflateDecoder::new(payload)
+        .read_to_end(&mut res)
+        .map_err(|e| KeyError::io(path, e))?;
+    Ok(res)
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress(path: &str, _payload: &[u8]) -> Result<Vec<u8>, KeyError> {
+    Err(KeyError::UnsupportedEncoding {
+        path: path.to_string(),
+        encoding: KeyEncoding::CompressedBinary,
+    })
+}
+
+/// Returns the paths of the key files with this prefix.
+pub(super) fn key_files(prefix: &str) -> Vec<String> {
+    [SECRET_FILE, BOOTSTRAPPING_FILE, KEYSWITCHING_FILE]
+        .iter()
+        .map(|file| format!("{}_{}", prefix, file))
+        .collect()
+}
+
+/// Returns the path of the optional public key file with this prefix.
+pub(super) fn public_key_file(prefix: &str) -> String {
+    format!("{}_{}", prefix, PUBLIC_FILE)
+}
+
+/// Returns the prefix of a key file name, if it is one.
+pub(super) fn key_file_prefix(file_name: &str) -> Option<&str> {
+    [
+        SECRET_FILE,
+        BOOTSTRAPPING_FILE,
+        KEYSWITCHING_FILE,
+        PUBLIC_FILE,
+    ]
+    .iter()
+    .find_map(|file| file_name.strip_suffix(file)?.strip_suffix('_'))
+}
+
+/// The key files of a key set found on disk, see `EncryptKey::key_set_state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySetState {
+    /// None of the key files exist.
+    Missing,
+    /// Only some of the key files exist, e.g. after an interrupted save: the key set can not be
+    /// loaded, and can be deleted.
+    Partial,
+    /// All the key files exist.
+    Complete,
+}
+
+/// Checks whether the key files with this prefix all exist without header, i.e. were written by
+/// the previous versions.
+pub(super) fn legacy_keys_exist(prefix: &str) -> bool {
+    key_files(prefix).iter().all(|path| {
+        matches!(
+            zqz::header::read_header(path),
+            Err(KeyError::MissingHeader(_))
+        )
+    })
+}
+
+/// Reads the headers of the key files with this prefix, without reading the keys, and checks that
+/// they belong to the same key set. Returns the header of the secret key.
+pub(super) fn read_key_set_header(prefix: &str) -> Result<KeyHeader, KeyError> {
+    if EncryptKey::key_set_state(prefix) == KeySetState::Partial {
+        return Err(KeyError::PartialKeySet(prefix.to_string()));
+    }
+    let mut headers: Vec<KeyHeader> = Vec::with_capacity(3);
+    for path in key_files(prefix) {
+        let header = zqz::header::read_header(&path)?;
+        if let Some(first) = headers.first() {
+            check_key_set(&path, first.key_set_id, &header)?;
+        }
+        headers.push(header);
+    }
+    Ok(headers.swap_remove(0))
+}
+
+// Checks that a key file belongs to the expected key set.
+fn check_key_set(path: &str, expected: u64, header: &KeyHeader) -> Result<(), KeyError> {
+    if header.key_set_id != expected {
+        return Err(KeyError::KeySetMismatch {
+            path: path.to_string(),
+            expected,
+            found: header.key_set_id,
+        });
     }
+    Ok(())
 }
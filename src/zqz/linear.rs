@@ -0,0 +1,97 @@
+//! A module providing linear operations between encrypted vectors and plaintext weights.
+use crate::zqz;
+use zqz::backend::RawCiphertext;
+use zqz::ciphertext::Ciphertext;
+use zqz::Parameters;
+
+/// Computes the dot product `sum_i cts[i] * weights[i] mod q` between an encrypted vector and a
+/// plaintext weight vector.
+///
+/// The null weights are dropped and the weights 1 are free, while every other weight costs the
+/// bootstrap of a multiplication by a constant: multiplying the LWE ciphertexts directly would
+/// spend the padding, which the encoders of the demo do not allow. The products are then summed
+/// by spending the padding, one bit for each sum of two terms, so with the two bits of padding of
+/// the parameter sets of the demo every sum after the first level of a pairwise summation costs
+/// bootstraps, on top of the final modulo bootstrap. See `bootstrap_cost` for the exact count.
+pub fn dot(cts: &[Ciphertext], weights: &[usize]) -> Ciphertext {
+    assert!(!cts.is_empty(), "cannot compute a dot product of empty vectors");
+    assert_eq!(
+        cts.len(),
+        weights.len(),
+        "the encrypted vector and the weights must have the same length"
+    );
+    cts.iter().for_each(|ct| cts[0].check_key(ct));
+    let evaluation_key = cts[0].evaluation_key.clone();
+    let q = evaluation_key.params.modulo;
+
+    let terms: Vec<RawCiphertext> = cts
+        .iter()
+        .zip(weights.iter().map(|w| w % q))
+        .filter(|(_, w)| *w != 0)
+        .map(|(ct, w)| scale(ct, w))
+        .collect();
+
+    if terms.is_empty() {
+        // all the weights are null
+        return cts[0].eval(|_| 0.);
+    }
+
+    Ciphertext {
+        ciphertext: zqz::ciphertext::sum_with_padding(terms, &evaluation_key),
+        evaluation_key,
+    }
+}
+
+/// Computes the product between a plaintext matrix, given as a list of rows, and an encrypted
+/// vector. Each row costs the bootstraps of a dot product, see `bootstrap_cost`.
+pub fn matvec(matrix: &[Vec<usize>], cts: &[Ciphertext]) -> Vec<Ciphertext> {
+    matrix.iter().map(|row| dot(cts, row)).collect()
+}
+
+/// Returns the number of bootstraps of a dot product between fresh encryptions and the weights,
+/// with the given parameters.
+pub fn bootstrap_cost(weights: &[usize], params: &Parameters) -> usize {
+    let padding = params.nb_bit_padding;
+
+    // the multiplications of the terms by their weights, which all keep a full padding
+    let weights: Vec<usize> = weights
+        .iter()
+        .map(|w| w % params.modulo)
+        .filter(|w| *w != 0)
+        .collect();
+    if weights.is_empty() {
+        return 1;
+    }
+    let mut cost = weights.iter().filter(|w| **w != 1).count();
+    let mut terms = vec![padding; weights.len()];
+
+    // the pairwise summation, see `sum_with_padding`
+    while terms.len() > 1 {
+        let mut next = Vec::with_capacity(terms.len().div_ceil(2));
+        for pair in terms.chunks(2) {
+            match *pair {
+                [left, right] => {
+                    let refresh = left < 2 || left != right;
+                    if refresh {
+                        cost += (left != padding) as usize + (right != padding) as usize;
+                    }
+                    let level = if refresh { padding } else { left };
+                    next.push(level - 1);
+                }
+                [left] => next.push(left),
+                _ => unreachable!(),
+            }
+        }
+        terms = next;
+    }
+    cost + 1
+}
+
+// Multiplies a ciphertext by a non null weight, with a bootstrap unless the weight is 1.
+fn scale(ct: &Ciphertext, weight: usize) -> RawCiphertext {
+    if weight == 1 {
+        ct.ciphertext.clone()
+    } else {
+        (ct * weight).ciphertext
+    }
+}
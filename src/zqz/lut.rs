@@ -0,0 +1,149 @@
+//! A module containing a lookup table structure, evaluated homomorphically with a bootstrap.
+use crate::zqz;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// A univariate function over Z/qZ, given by the list of its outputs.
+///
+/// A lookup table is built and validated once, and can then be applied to as many ciphertexts as
+/// needed with `Ciphertext::apply`, or saved and shipped as data.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<usize>", into = "Vec<usize>")]
+pub struct LookupTable {
+    table: Vec<usize>,
+}
+
+/// The errors raised when building an invalid lookup table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LookupTableError {
+    /// The table does not contain exactly q entries.
+    WrongSize { expected: usize, actual: usize },
+    /// An output of the table is not in Z/qZ.
+    OutOfRange { input: usize, output: usize },
+}
+
+impl fmt::Display for LookupTableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LookupTableError::WrongSize { expected, actual } => write!(
+                f,
+                "a lookup table needs {} entries, {} were given",
+                expected, actual
+            ),
+            LookupTableError::OutOfRange { input, output } => write!(
+                f,
+                "the output {} of the input {} is not smaller than the size of the table",
+                output, input
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LookupTableError {}
+
+impl LookupTable {
+    /// Builds a lookup table over Z/qZ from an explicit list of q outputs, where `table[x]` is the
+    /// image of `x`. The modulo q is the one of the ciphertexts the table is applied to.
+    pub fn new(table: &[usize], modulo: usize) -> Result<LookupTable, LookupTableError> {
+        if table.len() != modulo {
+            return Err(LookupTableError::WrongSize {
+                expected: modulo,
+                actual: table.len(),
+            });
+        }
+        if let Some((input, output)) = table
+            .iter()
+            .enumerate()
+            .find(|(_, output)| **output >= modulo)
+        {
+            return Err(LookupTableError::OutOfRange {
+                input,
+                output: *output,
+            });
+        }
+        Ok(LookupTable {
+            table: table.to_vec(),
+        })
+    }
+
+    /// Builds a lookup table over Z/qZ from an integer function, whose outputs are reduced
+    /// modulo q.
+    pub fn from_fn<F: Fn(usize) -> usize>(modulo: usize, f: F) -> LookupTable {
+        LookupTable {
+            table: (0..modulo).map(|x| f(x) % modulo).collect(),
+        }
+    }
+
+    /// Builds a lookup table over Z/qZ from a real function, with the same semantic as
+    /// `Ciphertext::eval`: the outputs are rounded and reduced modulo q.
+    pub fn from_f64_fn<F: Fn(f64) -> f64>(modulo: usize, f: F) -> LookupTable {
+        LookupTable {
+            table: (0..modulo)
+                .map(|x| zqz::utils::round_modulo(f(x as f64), modulo) as usize)
+                .collect(),
+        }
+    }
+
+    /// Returns the image of `x mod q`.
+    pub fn get(&self, x: usize) -> usize {
+        self.table[x % self.table.len()]
+    }
+
+    /// Returns the modulo q of the inputs and the outputs, i.e. the size of the table.
+    pub fn modulo(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Returns the outputs of the table.
+    pub fn as_slice(&self) -> &[usize] {
+        &self.table
+    }
+
+    /// Returns the lookup table computing `self(other(x))`.
+    pub fn compose(&self, other: &LookupTable) -> LookupTable {
+        LookupTable {
+            table: other.table.iter().map(|x| self.get(*x)).collect(),
+        }
+    }
+
+    /// Serializes the lookup table to a json string.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    /// Deserializes and validates a lookup table from a json string, whose modulo is the size of
+    /// the table.
+    pub fn from_json(s: &str) -> Result<LookupTable, String> {
+        serde_json::from_str(s).map_err(|e| e.to_string())
+    }
+}
+
+impl TryFrom<Vec<usize>> for LookupTable {
+    type Error = LookupTableError;
+
+    // The modulo is the size of the table, the ciphertexts check it when the table is applied.
+    fn try_from(table: Vec<usize>) -> Result<Self, Self::Error> {
+        LookupTable::new(&table, table.len())
+    }
+}
+
+impl From<LookupTable> for Vec<usize> {
+    fn from(lut: LookupTable) -> Self {
+        lut.table
+    }
+}
+
+// Prints the lookup table as a list of `x -> f(x)`.
+impl fmt::Display for LookupTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+        for (x, y) in self.table.iter().enumerate() {
+            if x > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} -> {}", x, y)?;
+        }
+        write!(f, "]")
+    }
+}
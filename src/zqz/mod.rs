@@ -1,14 +1,27 @@
 // A module allowing to perform encrypted computations on Z/qZ.
 use concrete::crypto_api;
 
+pub mod backend;
+pub mod boolean;
+pub mod circuit;
 pub mod ciphertext;
+pub mod estimator;
+pub mod expr;
+pub mod header;
 pub mod keys;
+pub mod linear;
+pub mod lut;
 pub mod max;
+pub mod optimizer;
+pub mod passphrase;
+pub mod public;
+pub mod store;
 pub mod utils;
 #[cfg(test)]
 mod tests;
 
 // A structure representing the parameters of the
+#[derive(Debug, PartialEq)]
 pub struct Parameters {
     pub nb_bit_precision: usize,
     pub modulo: usize,
@@ -50,6 +63,24 @@ impl Parameters {
             res
         }
     }
+
+    /// Returns the parameter set with this name, see `PRESETS`.
+    pub fn from_name(name: &str) -> Option<&'static Parameters> {
+        PRESETS.iter().find(|(n, _)| *n == name).map(|(_, p)| *p)
+    }
+
+    /// Returns the parameter set recorded in a key or ciphertext header, if it is a known one.
+    pub fn from_header(header: &header::ParametersHeader) -> Option<&'static Parameters> {
+        PRESETS
+            .iter()
+            .find(|(_, p)| header::ParametersHeader::from(*p) == *header)
+            .map(|(_, p)| *p)
+    }
+
+    /// Returns the name of the parameter set, if it is a known one.
+    pub fn name(&self) -> Option<&'static str> {
+        PRESETS.iter().find(|(_, p)| *p == self).map(|(n, _)| *n)
+    }
 }
 
 #[macro_export]
@@ -64,7 +95,7 @@ macro_rules! new_parameters {
         $rlwe_setting:expr,
         $lwe_setting: expr,
         $with_ks: expr) => {
-        crate::zqz::Parameters {
+        $crate::zqz::Parameters {
             nb_bit_precision: $nb_bit_precision,
             modulo: 1 << $nb_bit_precision,
             max: ((1 << $nb_bit_precision) - 1) as f64,
@@ -79,3 +110,46 @@ macro_rules! new_parameters {
         }
     };
 }
+
+// The parameters of Z/8Z, without keyswitch.
+pub const Z8Z: Parameters = new_parameters!(
+    3,
+    2,
+    6,
+    4,
+    1,
+    1,
+    crypto_api::RLWE128_1024_1,
+    crypto_api::LWE128_1024,
+    false
+);
+
+// The parameters of Z/8Z, with a keyswitch to a smaller lwe secret key.
+pub const Z8Z_KS: Parameters = new_parameters!(
+    3,
+    2,
+    7,
+    3,
+    2,
+    7,
+    crypto_api::RLWE128_1024_1,
+    crypto_api::LWE128_750,
+    true
+);
+
+// The parameters of Z/16Z, with a keyswitch to a smaller lwe secret key.
+pub const Z16Z_KS: Parameters = new_parameters!(
+    4,
+    2,
+    7,
+    3,
+    2,
+    7,
+    crypto_api::RLWE128_2048_1,
+    crypto_api::LWE128_750,
+    true
+);
+
+// The parameter sets known by name, e.g. on the command line.
+pub const PRESETS: [(&str, &Parameters); 3] =
+    [("z8z", &Z8Z), ("z8z-ks", &Z8Z_KS), ("z16z-ks", &Z16Z_KS)];
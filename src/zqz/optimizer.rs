@@ -0,0 +1,262 @@
+//! A module containing an optimizer of circuits, reducing the number of bootstraps.
+//!
+//! The optimizer rewrites a circuit into an equivalent one, where:
+//! - the operations on constants are computed once, in the clear,
+//! - the chains of univariate operations, i.e. the lookup tables and the additions,
+//!   multiplications and max with a constant, are fused into a single lookup table: `apply(f)`
+//!   followed by `apply(g)` becomes one lookup of `g∘f`, and `+ 2` followed by `* 3` becomes one
+//!   lookup of `3(x + 2)`,
+//! - a lookup table following an addition or a subtraction of two ciphertexts is applied by the
+//!   modulo bootstrap of the operation, see `Ciphertext::add_apply`,
+//! - the nodes no output depends on are removed.
+//!
+//! A value shared by several nodes is computed once, so that the optimized circuit never needs
+//! more bootstraps than the original one.
+use crate::zqz;
+use std::fmt;
+use zqz::circuit::{Circuit, Node, NodeId};
+use zqz::lut::LookupTable;
+
+/// The report of an optimization, comparing the circuits before and after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptimizationReport {
+    pub bootstraps_before: usize,
+    pub bootstraps_after: usize,
+    pub nodes_before: usize,
+    pub nodes_after: usize,
+}
+
+impl OptimizationReport {
+    /// Returns the number of bootstraps saved by the optimization.
+    pub fn bootstraps_saved(&self) -> usize {
+        self.bootstraps_before - self.bootstraps_after
+    }
+}
+
+impl fmt::Display for OptimizationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} bootstrap(s) instead of {} ({} saved), {} node(s) instead of {}",
+            self.bootstraps_after,
+            self.bootstraps_before,
+            self.bootstraps_saved(),
+            self.nodes_after,
+            self.nodes_before
+        )
+    }
+}
+
+// The encrypted argument of a lookup table not applied yet, as nodes of the optimized circuit.
+#[derive(Debug, Clone, Copy)]
+enum Argument {
+    Node(NodeId),
+    Sum(NodeId, NodeId),
+    Difference(NodeId, NodeId),
+}
+
+// The value of a node of the original circuit: a constant, or a lookup table applied lazily to an
+// argument, in order to be fused with the next univariate operations.
+//
+// A table made of additions of constants only is a translation, which is computed without
+// bootstrap like in the original circuit. Otherwise, the value is bootstrapped, which also reduces
+// it modulo q.
+#[derive(Debug, Clone)]
+enum Value {
+    Clear(usize),
+    Lazy {
+        argument: Argument,
+        table: Vec<usize>,
+        bootstrap: bool,
+    },
+}
+
+impl Value {
+    // Composes the value with a univariate function, `bootstrap` telling whether the function
+    // needs a bootstrap in the original circuit.
+    fn map<F: Fn(usize) -> usize>(&self, f: F, bootstrap: bool) -> Value {
+        match self {
+            Value::Clear(m) => Value::Clear(f(*m)),
+            Value::Lazy {
+                argument,
+                table,
+                bootstrap: b,
+            } => Value::Lazy {
+                argument: *argument,
+                table: table.iter().map(|y| f(*y)).collect(),
+                bootstrap: *b || bootstrap,
+            },
+        }
+    }
+}
+
+// Returns the constant c if the table is the translation x -> x + c.
+fn translation(table: &[usize]) -> Option<usize> {
+    let q = table.len();
+    let c = table[0];
+    if table.iter().enumerate().all(|(x, y)| *y == (x + c) % q) {
+        Some(c)
+    } else {
+        None
+    }
+}
+
+// The optimized circuit being built.
+struct Optimizer {
+    circuit: Circuit,
+}
+
+impl Optimizer {
+    // Returns the value of a node of the optimized circuit.
+    fn node(&self, id: NodeId) -> Value {
+        Value::Lazy {
+            argument: Argument::Node(id),
+            table: (0..self.circuit.modulo()).collect(),
+            bootstrap: false,
+        }
+    }
+
+    // Returns whether computing a value in the optimized circuit is free of bootstraps.
+    fn is_free(value: &Value) -> bool {
+        match value {
+            Value::Clear(_) => true,
+            Value::Lazy {
+                argument: Argument::Node(_),
+                table,
+                bootstrap,
+            } => !bootstrap || translation(table) == Some(0),
+            Value::Lazy { .. } => false,
+        }
+    }
+
+    // Adds the nodes computing a value to the optimized circuit. The nodes of the optimized
+    // circuit used as arguments are already reduced modulo q, so that a lookup table of the
+    // identity is not applied.
+    fn materialize(&mut self, value: &Value) -> NodeId {
+        let (argument, table, bootstrap) = match value {
+            Value::Clear(m) => return self.circuit.constant(*m),
+            Value::Lazy {
+                argument,
+                table,
+                bootstrap,
+            } => (*argument, table, *bootstrap),
+        };
+        let lut = LookupTable::new(table, self.circuit.modulo()).unwrap();
+        match (argument, bootstrap, translation(table)) {
+            (Argument::Node(a), _, Some(0)) => a,
+            (Argument::Node(a), false, Some(c)) => {
+                let c = self.circuit.constant(c);
+                self.circuit.add(a, c)
+            }
+            (Argument::Node(a), _, _) => self.circuit.apply(a, &lut),
+            (Argument::Sum(a, b), _, Some(0)) => self.circuit.add(a, b),
+            (Argument::Sum(a, b), _, _) => self.circuit.add_apply(a, b, &lut),
+            (Argument::Difference(a, b), _, Some(0)) => self.circuit.sub(a, b),
+            (Argument::Difference(a, b), _, _) => self.circuit.sub_apply(a, b, &lut),
+        }
+    }
+
+    // Returns the value of a binary operation, which is fused with its operands when one of them
+    // is a constant.
+    fn binary(&mut self, node: &Node, a: &Value, b: &Value) -> Value {
+        let q = self.circuit.modulo();
+        let translation = match node {
+            Node::Add(..) => true,
+            Node::Sub(..) => matches!(b, Value::Clear(_)),
+            _ => false,
+        };
+        match (a, b) {
+            (Value::Clear(a), Value::Clear(b)) => Value::Clear(node.eval_clear(&[*a, *b], q)),
+            (a, Value::Clear(b)) => a.map(|x| node.eval_clear(&[x, *b], q), !translation),
+            (Value::Clear(a), b) => b.map(|x| node.eval_clear(&[*a, x], q), !translation),
+            (a, b) => {
+                let a = self.materialize(a);
+                let b = self.materialize(b);
+                let argument = match node {
+                    Node::Add(..) => Argument::Sum(a, b),
+                    Node::Sub(..) => Argument::Difference(a, b),
+                    Node::Mul(..) => Argument::Node(self.circuit.mul(a, b)),
+                    _ => Argument::Node(self.circuit.max(a, b)),
+                };
+                Value::Lazy {
+                    argument,
+                    table: (0..q).collect(),
+                    bootstrap: false,
+                }
+            }
+        }
+    }
+}
+
+/// Returns a circuit computing the same outputs as the given one with fewer bootstraps, see the
+/// module documentation, and the report of the optimization.
+pub fn optimize(circuit: &Circuit) -> (Circuit, OptimizationReport) {
+    let q = circuit.modulo();
+    let nodes = circuit.nodes();
+    let live = circuit.live_nodes();
+
+    // the number of uses of each node, by the other nodes and the outputs
+    let mut uses = vec![0; nodes.len()];
+    for (i, node) in nodes.iter().enumerate() {
+        if live[i] {
+            node.operands().iter().for_each(|id| uses[id.index()] += 1);
+        }
+    }
+    circuit
+        .outputs()
+        .iter()
+        .for_each(|id| uses[id.index()] += 1);
+
+    let mut optimizer = Optimizer {
+        circuit: Circuit::with_modulo(q),
+    };
+    let mut values: Vec<Option<Value>> = vec![None; nodes.len()];
+    for (i, node) in nodes.iter().enumerate() {
+        // the unused inputs are kept, so that the optimized circuit takes the same inputs
+        if !live[i] && !matches!(node, Node::Input(_)) {
+            continue;
+        }
+        let operands: Vec<Value> = node
+            .operands()
+            .iter()
+            .map(|id| values[id.index()].clone().unwrap())
+            .collect();
+        let value = match (node, operands.as_slice()) {
+            (Node::Input(_), _) => {
+                let id = optimizer.circuit.input();
+                optimizer.node(id)
+            }
+            (Node::Constant(m), _) => Value::Clear(*m),
+            (Node::Apply(_, lut), [a]) => a.map(|x| lut.get(x), true),
+            (Node::AddApply(x, y, lut), [a, b]) => optimizer
+                .binary(&Node::Add(*x, *y), a, b)
+                .map(|x| lut.get(x), true),
+            (Node::SubApply(x, y, lut), [a, b]) => optimizer
+                .binary(&Node::Sub(*x, *y), a, b)
+                .map(|x| lut.get(x), true),
+            (_, [a, b]) => optimizer.binary(node, a, b),
+            _ => unreachable!(),
+        };
+
+        // a value used several times is computed once
+        values[i] = Some(if uses[i] > 1 && !Optimizer::is_free(&value) {
+            let id = optimizer.materialize(&value);
+            optimizer.node(id)
+        } else {
+            value
+        });
+    }
+    for id in circuit.outputs().iter() {
+        let value = values[id.index()].clone().unwrap();
+        let output = optimizer.materialize(&value);
+        optimizer.circuit.output(output);
+    }
+
+    let report = OptimizationReport {
+        bootstraps_before: circuit.bootstrap_cost(),
+        bootstraps_after: optimizer.circuit.bootstrap_cost(),
+        nodes_before: nodes.len(),
+        nodes_after: optimizer.circuit.nodes().len(),
+    };
+    (optimizer.circuit, report)
+}
@@ -0,0 +1,140 @@
+//! A module encrypting the secret key files under a passphrase.
+//!
+//! The encryption key is derived from the passphrase with argon2id and a random salt, and the
+//! payload of the file is encrypted with chacha20-poly1305. The header of the file is
+//! authenticated along with the payload, so that it can not be tampered with.
+use crate::zqz;
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use zeroize::Zeroizing;
+use zqz::keys::KeyError;
+
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+const KEY_SIZE: usize = 32;
+
+/// A passphrase, which is zeroized when dropped and redacted when debug-printed.
+#[derive(Clone, PartialEq)]
+pub struct Passphrase(Zeroizing<String>);
+
+impl Passphrase {
+    /// Wraps the given passphrase.
+    pub fn new(passphrase: &str) -> Passphrase {
+        Passphrase(Zeroizing::new(passphrase.to_string()))
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+impl fmt::Debug for Passphrase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Passphrase(<redacted>)")
+    }
+}
+
+/// The parameters of the encryption of a key file, recorded in its header.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyEncryption {
+    /// The hexadecimal salt of the key derivation.
+    pub salt: String,
+    /// The hexadecimal nonce of the authenticated encryption.
+    pub nonce: String,
+}
+
+// Derives the encryption key from the passphrase.
+fn derive_key(
+    path: &str,
+    passphrase: &Passphrase,
+    salt: &[u8],
+) -> Result<Zeroizing<[u8; KEY_SIZE]>, KeyError> {
+    let mut key = Zeroizing::new([0u8; KEY_SIZE]);
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut *key)
+        .map_err(|e| KeyError::Encryption {
+            path: path.to_string(),
+            reason: e.to_string(),
+        })?;
+    Ok(key)
+}
+
+// Draws random bytes from the operating system.
+fn random_bytes(path: &str, size: usize) -> Result<Vec<u8>, KeyError> {
+    let mut bytes = vec![0u8; size];
+    getrandom::getrandom(&mut bytes).map_err(|e| KeyError::Encryption {
+        path: path.to_string(),
+        reason: e.to_string(),
+    })?;
+    Ok(bytes)
+}
+
+// Decodes a hexadecimal field of the header.
+fn decode_hex(path: &str, field: &str) -> Result<Vec<u8>, KeyError> {
+    hex::decode(field).map_err(|e| KeyError::InvalidHeader {
+        path: path.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+/// Encrypts the payload of the key file `path` under the passphrase, authenticating the
+/// associated data along with it.
+pub fn encrypt(
+    path: &str,
+    passphrase: &Passphrase,
+    payload: &[u8],
+    associated_data: &[u8],
+) -> Result<(KeyEncryption, Vec<u8>), KeyError> {
+    let salt = random_bytes(path, SALT_SIZE)?;
+    let nonce = random_bytes(path, NONCE_SIZE)?;
+    let key = derive_key(path, passphrase, &salt)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&*key));
+    let encrypted = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: payload,
+                aad: associated_data,
+            },
+        )
+        .map_err(|e| KeyError::Encryption {
+            path: path.to_string(),
+            reason: e.to_string(),
+        })?;
+    let encryption = KeyEncryption {
+        salt: hex::encode(salt),
+        nonce: hex::encode(nonce),
+    };
+    Ok((encryption, encrypted))
+}
+
+/// Decrypts the payload of the key file `path` with the passphrase, checking the associated data.
+pub fn decrypt(
+    path: &str,
+    passphrase: &Passphrase,
+    encryption: &KeyEncryption,
+    payload: &[u8],
+    associated_data: &[u8],
+) -> Result<Vec<u8>, KeyError> {
+    let salt = decode_hex(path, &encryption.salt)?;
+    let nonce = decode_hex(path, &encryption.nonce)?;
+    if nonce.len() != NONCE_SIZE {
+        return Err(KeyError::InvalidHeader {
+            path: path.to_string(),
+            reason: format!("the nonce must be {} bytes long", NONCE_SIZE),
+        });
+    }
+    let key = derive_key(path, passphrase, &salt)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&*key));
+    cipher
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: payload,
+                aad: associated_data,
+            },
+        )
+        .map_err(|_| KeyError::WrongPassphrase(path.to_string()))
+}
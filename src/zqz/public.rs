@@ -0,0 +1,140 @@
+//! A module containing a public key, allowing untrusted parties to encrypt messages.
+//!
+//! The public key is a list of encryptions of zero under the secret key. A message is encrypted
+//! by summing a random subset of these encryptions and adding the message to the result, so that
+//! the ciphertexts can be decrypted by the secret key holder and used with all the homomorphic
+//! operators.
+use crate::zqz;
+use concrete::crypto_api;
+use std::rc::Rc;
+use zqz::backend::RawCiphertext;
+use zqz::ciphertext::Ciphertext;
+use zqz::header::{KeyEncoding, KeyHeader, KeyKind};
+use zqz::keys::{EncryptKey, HomomorphicKey, KeyError};
+use zqz::Parameters;
+
+/// A public key, made of encryptions of zero and of the evaluation keys.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PublicKey {
+    zeros: Vec<crypto_api::LWE>,
+    evaluation: Rc<HomomorphicKey>,
+}
+
+/// The security level of the public key encryption, in bits.
+pub const SECURITY_BITS: usize = 128;
+
+// The number of bits of the modulus of the ciphertexts, which are vectors of u64.
+const MODULUS_BITS: usize = 64;
+
+impl PublicKey {
+    /// Returns the minimal number of encryptions of zero of a secure public key with these
+    /// parameters, `(n + 1)·log2(q) + 2λ` with `n` the dimension of the lwe secret key, `q` the
+    /// modulus of the ciphertexts and `λ` the security level. Below it, the random subset sums are
+    /// not guaranteed to hide the secret key by the leftover hash lemma.
+    pub fn min_size(params: &Parameters) -> usize {
+        (params.lwe_setting.dimension + 1) * MODULUS_BITS + 2 * SECURITY_BITS
+    }
+
+    /// Generates a public key made of `size` encryptions of zero under the secret key, which must
+    /// be at least `min_size`. The key set must belong to the LWE backend.
+    ///
+    /// The noise of a ciphertext grows with the square root of `size`, so the public key should
+    /// not be much larger than needed.
+    pub fn new(sk: &EncryptKey, size: usize) -> Result<PublicKey, KeyError> {
+        let min_size = PublicKey::min_size(sk.params());
+        if size < min_size {
+            return Err(KeyError::InsecurePublicKey { size, min_size });
+        }
+        let (secret, _) = sk.lwe_keys()?;
+        let encoder = zqz::utils::new_encoder(sk.params());
+        let zeros = (0..size)
+            .map(|_| crypto_api::LWE::encode_encrypt(secret, 0., &encoder).unwrap())
+            .collect();
+        Ok(PublicKey {
+            zeros,
+            evaluation: sk.evaluation.clone(),
+        })
+    }
+
+    /// Returns the number of encryptions of zero of the public key.
+    pub fn size(&self) -> usize {
+        self.zeros.len()
+    }
+
+    /// Returns the parameters of the key set of the public key.
+    pub fn params(&self) -> &'static Parameters {
+        self.evaluation.params
+    }
+
+    /// Encrypts the given message. An error is returned if the random subset can not be drawn.
+    pub fn encrypt(&self, message: usize) -> Result<Ciphertext, KeyError> {
+        let m = message % self.evaluation.params.modulo;
+
+        // random subset sum of the encryptions of zero
+        let mut selection = vec![0u8; self.zeros.len()];
+        getrandom::getrandom(&mut selection)
+            .map_err(|e| KeyError::PublicEncryption(e.to_string()))?;
+        let mut ct = self.zeros[0].clone();
+        for (zero, selected) in self.zeros[1..].iter().zip(selection[1..].iter()) {
+            if selected & 1 == 1 {
+                ct.add_with_new_min_inplace(zero, 0.)
+                    .map_err(|e| KeyError::PublicEncryption(e.to_string()))?;
+            }
+        }
+
+        // message
+        ct.add_constant_static_encoder_inplace(m as f64)
+            .map_err(|e| KeyError::PublicEncryption(e.to_string()))?;
+        Ok(Ciphertext {
+            ciphertext: RawCiphertext::Lwe(ct),
+            evaluation_key: self.evaluation.clone(),
+        })
+    }
+
+    /// Saves the encryptions of zero of the public key to a file with this prefix. The evaluation
+    /// keys are saved along the secret key, see `EncryptKey::save_to_files`.
+    pub fn save_to_file(&self, prefix: &str) -> Result<(), KeyError> {
+        let header = KeyHeader::new(
+            KeyKind::Public,
+            self.evaluation.params,
+            self.evaluation.id,
+            KeyEncoding::Binary,
+        );
+        let payload = bincode::serialize(&self.zeros).unwrap();
+        zqz::header::write_key_file(&zqz::keys::public_key_file(prefix), header, &payload)
+    }
+
+    /// Loads the public key and the evaluation keys with this prefix, without the secret key. The
+    /// parameters are the ones recorded in the header of the public key.
+    pub fn load_from_files(prefix: &str) -> Result<PublicKey, KeyError> {
+        let path = zqz::keys::public_key_file(prefix);
+        let params = Parameters::from_header(&zqz::header::read_header(&path)?.parameters)
+            .ok_or_else(|| KeyError::InvalidHeader {
+                path: path.clone(),
+                reason: "unknown parameters".to_string(),
+            })?;
+        let (header, payload) = zqz::header::read_key_file(&path, KeyKind::Public, params)?;
+        let zeros: Vec<crypto_api::LWE> =
+            bincode::deserialize(&payload).map_err(|e| KeyError::InvalidKey {
+                path: path.clone(),
+                reason: e.to_string(),
+            })?;
+        let min_size = PublicKey::min_size(params);
+        if zeros.len() < min_size {
+            return Err(KeyError::InvalidKey {
+                path,
+                reason: format!(
+                    "the public key has {} encryptions of zero, at least {} are needed",
+                    zeros.len(),
+                    min_size
+                ),
+            });
+        }
+        let evaluation =
+            HomomorphicKey::load_from_files_with_params(prefix, params, header.key_set_id)?;
+        Ok(PublicKey {
+            zeros,
+            evaluation: Rc::new(evaluation),
+        })
+    }
+}
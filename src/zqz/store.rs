@@ -0,0 +1,248 @@
+//! A module containing a key store, managing the key sets saved in a directory.
+use crate::zqz;
+use crate::PARAMS;
+use std::fs;
+use std::path::{Path, PathBuf};
+use zqz::header::{KeyEncoding, ParametersHeader};
+use zqz::keys::{EncryptKey, HomomorphicKey, KeyError, KeySetState};
+use zqz::passphrase::Passphrase;
+use zqz::public::PublicKey;
+use zqz::Parameters;
+
+/// The environment variable used to set the directory of the key store.
+pub const KEY_DIR_VAR: &str = "DEMO_Z8Z_KEY_DIR";
+
+/// The environment variable used by the command line to set the passphrase of the secret keys.
+pub const PASSPHRASE_VAR: &str = "DEMO_Z8Z_PASSPHRASE";
+
+/// A directory containing key sets, saved under the prefix given by `Parameters::gen_prefix`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyStore {
+    dir: PathBuf,
+    encoding: KeyEncoding,
+    passphrase: Option<Passphrase>,
+}
+
+/// A key set found in a key store.
+#[derive(Debug)]
+pub struct KeySetEntry {
+    /// The prefix of the key files.
+    pub prefix: String,
+    /// The parameters and the identifier of the key set, or the reason why the key set is not
+    /// usable (missing file, corrupted header, mismatching key sets...).
+    pub status: Result<(ParametersHeader, u64), KeyError>,
+}
+
+impl KeyStore {
+    /// Opens the key store located in `dir`, creating the directory if needed.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<KeyStore, KeyError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(|e| KeyError::io(&dir.to_string_lossy(), e))?;
+        Ok(KeyStore {
+            dir,
+            encoding: KeyEncoding::Binary,
+            passphrase: None,
+        })
+    }
+
+    /// Sets the encoding used to save the keys, binary by default.
+    pub fn with_encoding(mut self, encoding: KeyEncoding) -> KeyStore {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Sets the passphrase used to encrypt and decrypt the secret keys of the store.
+    pub fn with_passphrase(mut self, passphrase: Passphrase) -> KeyStore {
+        self.passphrase = Some(passphrase);
+        self
+    }
+
+    /// Opens the key store located in the directory given by the `DEMO_Z8Z_KEY_DIR` environment
+    /// variable, or in the current directory if it is not set.
+    pub fn from_env() -> Result<KeyStore, KeyError> {
+        match std::env::var_os(KEY_DIR_VAR) {
+            Some(dir) => KeyStore::new(dir),
+            None => KeyStore::new("."),
+        }
+    }
+
+    /// Returns the directory of the key store.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // Returns the path prefix of the key files for these parameters.
+    fn prefix(&self, params: &Parameters) -> String {
+        self.dir
+            .join(params.gen_prefix())
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Checks whether all the key files exist for these parameters. They may not form a usable
+    /// key set, which is reported by `load`.
+    pub fn contains(&self, params: &Parameters) -> bool {
+        self.state(params) == KeySetState::Complete
+    }
+
+    /// Checks which key files exist for these parameters.
+    pub fn state(&self, params: &Parameters) -> KeySetState {
+        EncryptKey::key_set_state(&self.prefix(params))
+    }
+
+    /// Loads the key set generated with these parameters. A key set written without headers by
+    /// the previous versions is migrated, i.e. saved again with headers.
+    pub fn load(&self, params: &'static Parameters) -> Result<EncryptKey, KeyError> {
+        let prefix = self.prefix(params);
+        if self.state(params) == KeySetState::Partial {
+            return Err(KeyError::PartialKeySet(prefix));
+        }
+        match EncryptKey::load_from_files_with_params(&prefix, params, self.passphrase.as_ref()) {
+            Err(KeyError::MissingHeader(_)) if zqz::keys::legacy_keys_exist(&prefix) => {
+                let key = EncryptKey::load_from_legacy_files(&prefix, params)?;
+                self.save(params, &key)?;
+                Ok(key)
+            }
+            res => res,
+        }
+    }
+
+    /// Loads the evaluation keys of the key set generated with these parameters, without the
+    /// secret key, checking that they belong to the key set `id`.
+    pub fn load_evaluation_key(
+        &self,
+        params: &'static Parameters,
+        id: u64,
+    ) -> Result<HomomorphicKey, KeyError> {
+        HomomorphicKey::load_from_files_with_params(&self.prefix(params), params, id)
+    }
+
+    /// Saves a key set generated with these parameters.
+    pub fn save(&self, params: &Parameters, key: &EncryptKey) -> Result<(), KeyError> {
+        key.save_to_files_with_params(
+            &self.prefix(params),
+            params,
+            self.encoding,
+            self.passphrase.as_ref(),
+        )
+    }
+
+    /// Saves a public key along its key set.
+    pub fn save_public_key(&self, key: &PublicKey) -> Result<(), KeyError> {
+        key.save_to_file(&self.prefix(key.params()))
+    }
+
+    /// Loads the public key of the key set generated with these parameters, along with its
+    /// evaluation keys, without the secret key.
+    pub fn load_public_key(&self, params: &Parameters) -> Result<PublicKey, KeyError> {
+        PublicKey::load_from_files(&self.prefix(params))
+    }
+
+    /// Loads the key set of the current parameters, or generates and saves it if it does not
+    /// exist yet.
+    pub fn load_or_generate(&self) -> Result<EncryptKey, KeyError> {
+        self.load_or_generate_with_params(&PARAMS)
+    }
+
+    /// Loads the key set of these parameters, or generates and saves it if none of its files
+    /// exist yet. A partial key set, e.g. left by an interrupted save, is deleted and replaced, while
+    /// a complete but unusable key set is reported as an error.
+    pub fn load_or_generate_with_params(
+        &self,
+        params: &'static Parameters,
+    ) -> Result<EncryptKey, KeyError> {
+        match self.state(params) {
+            KeySetState::Complete => self.load(params),
+            state => {
+                if state == KeySetState::Partial {
+                    self.delete(&params.gen_prefix())?;
+                }
+                let key = EncryptKey::with_params(params);
+                self.save(params, &key)?;
+                Ok(key)
+            }
+        }
+    }
+
+    /// Loads the key set of the current parameters derived from the seed, or generates and saves
+    /// it if it does not exist yet. The seed is recorded in the prefix of the key files, and the
+    /// identifier of the key set. **For tests only**, see `EncryptKey::from_seed`.
+    #[cfg(test)]
+    pub fn load_or_generate_seeded(&self, seed: u64) -> Result<EncryptKey, KeyError> {
+        let prefix = self
+            .dir
+            .join(format!("seed_{}_{}", seed, PARAMS.gen_prefix()))
+            .to_string_lossy()
+            .into_owned();
+        if EncryptKey::key_set_state(&prefix) == KeySetState::Complete {
+            let key = EncryptKey::load_from_files_with_params(
+                &prefix,
+                &PARAMS,
+                self.passphrase.as_ref(),
+            )?;
+            if key.key_id() == seed {
+                return Ok(key);
+            }
+        }
+        let key = EncryptKey::from_seed(seed, &PARAMS);
+        key.save_to_files_with_params(&prefix, &PARAMS, self.encoding, self.passphrase.as_ref())?;
+        Ok(key)
+    }
+
+    /// Lists the key sets of the store, including the unusable ones.
+    pub fn list(&self) -> Result<Vec<KeySetEntry>, KeyError> {
+        let dir = self.dir.to_string_lossy();
+        let mut prefixes: Vec<String> = Vec::new();
+        for entry in fs::read_dir(&self.dir).map_err(|e| KeyError::io(&dir, e))? {
+            let entry = entry.map_err(|e| KeyError::io(&dir, e))?;
+            let file_name = entry.file_name();
+            if let Some(prefix) = zqz::keys::key_file_prefix(&file_name.to_string_lossy()) {
+                if !prefixes.iter().any(|p| p == prefix) {
+                    prefixes.push(prefix.to_string());
+                }
+            }
+        }
+        prefixes.sort();
+
+        Ok(prefixes
+            .into_iter()
+            .map(|prefix| {
+                let path = self.dir.join(&prefix).to_string_lossy().into_owned();
+                let status = zqz::keys::read_key_set_header(&path)
+                    .map(|header| (header.parameters, header.key_set_id));
+                KeySetEntry { prefix, status }
+            })
+            .collect())
+    }
+
+    /// Deletes the key set saved with this prefix.
+    pub fn delete(&self, prefix: &str) -> Result<(), KeyError> {
+        let prefix = self.dir.join(prefix).to_string_lossy().into_owned();
+        let mut paths = zqz::keys::key_files(&prefix);
+        paths.push(zqz::keys::public_key_file(&prefix));
+        for path in paths {
+            if Path::new(&path).exists() {
+                fs::remove_file(&path).map_err(|e| KeyError::io(&path, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes the key sets which are not usable, or which were not generated with one of the
+    /// parameters to keep. Returns the prefixes of the deleted key sets.
+    pub fn delete_stale(&self, keep: &[&Parameters]) -> Result<Vec<String>, KeyError> {
+        let keep: Vec<ParametersHeader> = keep.iter().map(|p| ParametersHeader::from(*p)).collect();
+        let mut deleted = Vec::new();
+        for entry in self.list()? {
+            let stale = match &entry.status {
+                Ok((params, _)) => !keep.contains(params),
+                Err(_) => true,
+            };
+            if stale {
+                self.delete(&entry.prefix)?;
+                deleted.push(entry.prefix);
+            }
+        }
+        Ok(deleted)
+    }
+}
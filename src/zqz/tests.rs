@@ -1,7 +1,38 @@
 use crate::PARAMS;
 use crate::zqz;
-use crate::zqz::keys::EncryptKey;
+use crate::zqz::keys::{ConversionKey, EncryptKey};
+use crate::zqz::public::PublicKey;
+use crate::zqz::store::KeyStore;
 use concrete::core_api::math::Random;
+use rand_core::RngCore;
+use std::cell::RefCell;
+
+/// The environment variable used to seed the tests, so that a failure can be reproduced.
+const TEST_SEED_VAR: &str = "DEMO_Z8Z_TEST_SEED";
+
+/// The environment variable giving the number of runs of each input of the exhaustive tests.
+const EXHAUSTIVE_RUNS_VAR: &str = "DEMO_Z8Z_EXHAUSTIVE_RUNS";
+
+// Returns the seed given in the environment, if any.
+fn env_seed() -> Option<u64> {
+    std::env::var(TEST_SEED_VAR)
+        .ok()
+        .map(|seed| seed.parse().expect("the test seed must be an integer"))
+}
+
+thread_local! {
+    // The generator of the random messages of the tests, seeded from the environment or from a
+    // random seed which is printed.
+    static RNG: RefCell<rand_chacha::ChaCha20Rng> = RefCell::new({
+        let seed = env_seed().unwrap_or_else(|| {
+            let mut rs = vec![0_u64; 1];
+            Random::rng_uniform(&mut rs);
+            rs[0]
+        });
+        println!("test seed: {} (set {} to reproduce)", seed, TEST_SEED_VAR);
+        zqz::utils::seeded_rng(seed)
+    });
+}
 
 #[allow(unused_macros)]
 macro_rules! random_index {
@@ -9,13 +40,23 @@ macro_rules! random_index {
         if $max == 0 {
             (0 as usize)
         } else {
-            let mut rs = vec![0 as u32; 1];
-            Random::rng_uniform(&mut rs);
-            (rs[0] % ($max as u32)) as usize
+            let r = RNG.with(|rng| rng.borrow_mut().next_u32());
+            (r % ($max as u32)) as usize
         }
     }};
 }
 
+// Returns the key of the tests, loaded from the key store or generated and saved in it: derived
+// from the seed given in the environment if any, so that the failures can be reproduced.
+fn test_key() -> EncryptKey {
+    KeyStore::from_env()
+        .and_then(|store| match env_seed() {
+            Some(seed) => store.load_or_generate_seeded(seed),
+            None => store.load_or_generate(),
+        })
+        .unwrap_or_else(|e| panic!("{}", e))
+}
+
 fn test_encrypt_decrypt(i: usize, sk: &EncryptKey) -> usize {
     // generate random messages
     let m = random_index!(PARAMS.modulo);
@@ -397,7 +438,7 @@ fn test_max(i: usize, sk: &EncryptKey) -> usize {
     use zqz::max::max;
 
     // generate random messages
-    let m1 = 0; //random_index!(PARAMS.modulo);
+    let m1 = random_index!(PARAMS.modulo);
     let m2 = random_index!(PARAMS.modulo);
 
     // encryption
@@ -456,6 +497,109 @@ fn test_max_cst(i: usize, sk: &EncryptKey) -> usize {
     0
 }
 
+fn test_max_translated(i: usize, sk: &EncryptKey) -> usize {
+    use zqz::ciphertext::bootstrap_count;
+    use zqz::circuit::Circuit;
+    use zqz::max::max;
+
+    // generate random messages
+    let q = PARAMS.modulo;
+    let m1 = random_index!(q);
+    let m2 = random_index!(q);
+    let c = random_index!(q);
+
+    // encryption, the first message being translated without bootstrap
+    let ct1 = &sk.encrypt(m1) + c;
+    let ct2 = sk.encrypt(m2);
+
+    // homomorphic evaluation
+    let ct3 = max(&ct1, &ct2);
+    let ct4 = max(&ct1, m2);
+
+    // the same computation as a circuit, whose bootstrap cost accounts for the translation
+    let mut circuit = Circuit::new();
+    let a = circuit.input();
+    let b = circuit.input();
+    let constant = circuit.constant(c);
+    let translated = circuit.add(a, constant);
+    let res = circuit.max(translated, b);
+    circuit.output(res);
+    let before = bootstrap_count();
+    let outputs = circuit.run(&[sk.encrypt(m1), ct2]).unwrap();
+    let bootstraps = bootstrap_count() - before;
+
+    // decryption
+    let m3 = sk.decrypt(&ct3);
+    let m4 = sk.decrypt(&ct4);
+    let m5 = sk.decrypt(&outputs[0]);
+
+    // test
+    let expected = usize::max((m1 + c) % q, m2);
+    if m3 != expected || m4 != expected || m5 != expected || bootstraps != circuit.bootstrap_cost()
+    {
+        println!(
+            "test_max_translated[{}]: max({} + {}, {}) = {} != ({}, {}, {}) (obtained after \
+             decryption) with {} bootstrap(s) instead of {}",
+            i,
+            m1,
+            c,
+            m2,
+            expected,
+            m3,
+            m4,
+            m5,
+            bootstraps,
+            circuit.bootstrap_cost()
+        );
+        return 1;
+    }
+    0
+}
+
+fn test_translated_operators(i: usize, sk: &EncryptKey) -> usize {
+    use zqz::lut::LookupTable;
+    use zqz::max::max;
+
+    // generate random messages
+    let q = PARAMS.modulo;
+    let m1 = random_index!(q);
+    let m2 = random_index!(q);
+    let c = random_index!(q);
+    let lut = LookupTable::from_fn(q, |x| x * x % q);
+
+    // encryption, the first message being translated without bootstrap
+    let ct1 = &sk.encrypt(m1) + c;
+    let ct2 = sk.encrypt(m2);
+
+    // homomorphic evaluation, whose results are encoded like fresh encryptions
+    let m = (m1 + c) % q;
+    let results = [
+        ("+", &ct1 + &ct2, (m + m2) % q),
+        ("-", &ct1 - &ct2, (m + q - m2) % q),
+        ("*", &ct1 * &ct2, m * m2 % q),
+        ("* cst", &ct1 * m2, m * m2 % q),
+        ("max cst", max(&ct1, m2), usize::max(m, m2)),
+        ("apply", ct1.apply(&lut), m * m % q),
+        ("eval", ct1.eval(|x| x + 1.), (m + 1) % q),
+    ];
+
+    // decryption and test
+    let mut errors = 0;
+    for (op, ct, expected) in results.iter() {
+        let res = sk.decrypt(ct);
+        let fresh = *ct.ciphertext.encoder() == ct.fresh_encoder();
+        if res != *expected || !fresh {
+            println!(
+                "test_translated_operators[{}]: {} of ({} + {}, {}) = {} != {} (obtained after \
+                 decryption), encoded like a fresh encryption: {}",
+                i, op, m1, c, m2, expected, res, fresh
+            );
+            errors += 1;
+        }
+    }
+    errors
+}
+
 fn test_max_cst_rev(i: usize, sk: &EncryptKey) -> usize {
     use zqz::max::max;
 
@@ -487,16 +631,1712 @@ fn test_max_cst_rev(i: usize, sk: &EncryptKey) -> usize {
     0
 }
 
+fn test_eval_poly(i: usize, sk: &EncryptKey) -> usize {
+    // generate random messages
+    let m1 = random_index!(PARAMS.modulo);
+    let coefs = [
+        random_index!(PARAMS.modulo),
+        random_index!(PARAMS.modulo),
+        random_index!(PARAMS.modulo),
+    ];
+
+    // encryption
+    let ct1 = sk.encrypt(m1);
+
+    // homomorphic evaluation
+    let ct3 = ct1.eval_poly(&coefs);
+
+    // decryption
+    let m: usize = sk.decrypt(&ct3);
+
+    // test
+    let expected = (coefs[0] + coefs[1] * m1 + coefs[2] * m1 * m1) % PARAMS.modulo;
+    if expected != m {
+        println!(
+            "test_eval_poly[{}]: {:?}({}) = {} != {} (obtained after decryption)",
+            i, coefs, m1, expected, m
+        );
+        return 1;
+    }
+    0
+}
+
+fn test_eval_poly_sum(i: usize, sk: &EncryptKey) -> usize {
+    // generate random messages
+    let m1 = random_index!(PARAMS.modulo);
+    let m2 = random_index!(PARAMS.modulo);
+    let m3 = random_index!(PARAMS.modulo);
+    let coefs1 = [random_index!(PARAMS.modulo), random_index!(PARAMS.modulo)];
+    let coefs2 = [0, 0, random_index!(PARAMS.modulo)];
+    let coefs3 = [0, random_index!(PARAMS.modulo)];
+
+    // encryption
+    let ct1 = sk.encrypt(m1);
+    let ct2 = sk.encrypt(m2);
+    let ct3 = sk.encrypt(m3);
+
+    // homomorphic evaluation
+    let ct4 = zqz::ciphertext::Ciphertext::eval_poly_sum(&[
+        (&ct1, &coefs1[..]),
+        (&ct2, &coefs2[..]),
+        (&ct3, &coefs3[..]),
+    ]);
+
+    // decryption
+    let m: usize = sk.decrypt(&ct4);
+
+    // test
+    let expected =
+        (coefs1[0] + coefs1[1] * m1 + coefs2[2] * m2 * m2 + coefs3[1] * m3) % PARAMS.modulo;
+    if expected != m {
+        println!(
+            "test_eval_poly_sum[{}]: {:?}({}) + {:?}({}) + {:?}({}) = {} != {} (obtained after decryption)",
+            i, coefs1, m1, coefs2, m2, coefs3, m3, expected, m
+        );
+        return 1;
+    }
+    0
+}
+
+fn test_dot(i: usize, sk: &EncryptKey) -> usize {
+    use zqz::ciphertext::bootstrap_count;
+    use zqz::linear::{bootstrap_cost, dot};
+
+    // generate random messages
+    let messages: Vec<usize> = (0..4).map(|_| random_index!(PARAMS.modulo)).collect();
+    let weights: Vec<usize> = (0..4).map(|_| random_index!(PARAMS.modulo)).collect();
+
+    // encryption
+    let cts: Vec<_> = messages.iter().map(|m| sk.encrypt(*m)).collect();
+
+    // homomorphic evaluation
+    let before = bootstrap_count();
+    let ct = dot(&cts, &weights);
+    let bootstraps = bootstrap_count() - before;
+
+    // decryption
+    let m: usize = sk.decrypt(&ct);
+
+    // test
+    let expected = messages
+        .iter()
+        .zip(weights.iter())
+        .fold(0, |acc, (m, w)| (acc + m * w) % PARAMS.modulo);
+    let cost = bootstrap_cost(&weights, &PARAMS);
+    if expected != m || bootstraps != cost {
+        println!(
+            "test_dot[{}]: {:?} . {:?} = {} != {} (obtained after decryption) with {} \
+             bootstrap(s) instead of {}",
+            i, messages, weights, expected, m, bootstraps, cost
+        );
+        return 1;
+    }
+    0
+}
+
+fn test_apply(i: usize, sk: &EncryptKey) -> usize {
+    use zqz::lut::LookupTable;
+
+    // generate random messages
+    let m1 = random_index!(PARAMS.modulo);
+    let table: Vec<usize> = (0..PARAMS.modulo)
+        .map(|_| random_index!(PARAMS.modulo))
+        .collect();
+    let lut = LookupTable::new(&table, PARAMS.modulo).unwrap();
+
+    // encryption
+    let ct1 = sk.encrypt(m1);
+
+    // homomorphic evaluation
+    let ct3 = ct1.apply(&lut);
+
+    // decryption
+    let m: usize = sk.decrypt(&ct3);
+
+    // test
+    if table[m1] != m {
+        println!(
+            "test_apply[{}]: {}({}) = {} != {} (obtained after decryption)",
+            i, lut, m1, table[m1], m
+        );
+        return 1;
+    }
+    0
+}
+
 #[test]
-fn test_homomorphic_key() {
-    let sk = if !EncryptKey::keys_exist(&PARAMS.gen_prefix()) {
-            let key = EncryptKey::new();
-            key.save_to_files(&PARAMS.gen_prefix());
-            key
-        } else {
-            EncryptKey::load_from_files(&PARAMS.gen_prefix())
+fn test_dot_cost() {
+    use zqz::linear::bootstrap_cost;
+
+    // with two bits of padding, the weights 1 and the first level of sums are free
+    for params in [&zqz::Z8Z, &zqz::Z8Z_KS, &zqz::Z16Z_KS].iter() {
+        assert_eq!(bootstrap_cost(&[0, 0], params), 1);
+        assert_eq!(bootstrap_cost(&[1, 1], params), 1);
+        assert_eq!(bootstrap_cost(&[1, 0, 1, 1], params), 2);
+        assert_eq!(bootstrap_cost(&[1, 1, 1, 1], params), 3);
+        assert_eq!(bootstrap_cost(&[2, 3], params), 3);
+        assert_eq!(bootstrap_cost(&[2, 3, 1, 5], params), 6);
+    }
+}
+
+#[test]
+fn test_lookup_table() {
+    use zqz::lut::{LookupTable, LookupTableError};
+
+    // validation
+    assert_eq!(
+        LookupTable::new(&[0; 2], PARAMS.modulo),
+        Err(LookupTableError::WrongSize {
+            expected: PARAMS.modulo,
+            actual: 2
+        })
+    );
+    let mut table: Vec<usize> = (0..PARAMS.modulo).collect();
+    table[1] = PARAMS.modulo;
+    assert_eq!(
+        LookupTable::new(&table, PARAMS.modulo),
+        Err(LookupTableError::OutOfRange {
+            input: 1,
+            output: PARAMS.modulo
+        })
+    );
+
+    // construction from a closure
+    let cube = LookupTable::from_f64_fn(PARAMS.modulo, |x| f64::powi(x, 3));
+    for x in 0..PARAMS.modulo {
+        assert_eq!(cube.get(x), x * x * x % PARAMS.modulo);
+    }
+
+    // serialization
+    let json = cube.to_json();
+    assert_eq!(LookupTable::from_json(&json).unwrap(), cube);
+    assert!(LookupTable::from_json("[1, 2]").is_err());
+
+    // the modulo of a deserialized table is its size
+    assert_eq!(LookupTable::from_json("[1, 0]").unwrap().modulo(), 2);
+}
+
+fn test_eval_many(i: usize, sk: &EncryptKey) -> usize {
+    use zqz::ciphertext::bootstrap_count;
+
+    // generate random messages
+    let m1 = random_index!(PARAMS.modulo);
+    let m2 = random_index!(PARAMS.modulo);
+
+    // encryption
+    let ct1 = sk.encrypt(m1);
+
+    // homomorphic evaluation
+    let square = |x: f64| x * x;
+    let shift = |x: f64| x + m2 as f64;
+    let before = bootstrap_count();
+    let cts = ct1.eval_many(&[&square, &shift, &square]);
+    let bootstraps = bootstrap_count() - before;
+
+    // decryption
+    let m: Vec<usize> = cts.iter().map(|ct| sk.decrypt(ct)).collect();
+
+    // test
+    let expected = vec![
+        (m1 * m1) % PARAMS.modulo,
+        (m1 + m2) % PARAMS.modulo,
+        (m1 * m1) % PARAMS.modulo,
+    ];
+    // the two distinct functions cost one bootstrap each
+    if expected != m || bootstraps != 2 {
+        println!(
+            "test_eval_many[{}]: [{}^2, {} + {}, {}^2] = {:?} != {:?} (obtained after decryption) \
+             with {} bootstrap(s) instead of 2",
+            i, m1, m1, m2, m1, expected, m, bootstraps
+        );
+        return 1;
+    }
+    0
+}
+
+#[test]
+fn test_eval_many_other_params() {
+    // the functions are tabulated over the Z/qZ of the ciphertext, not the one of the build
+    let sk = KeyStore::from_env()
+        .and_then(|store| store.load_or_generate_with_params(other_params()))
+        .unwrap_or_else(|e| panic!("{}", e));
+    let q = sk.params().modulo;
+    let succ = |x: f64| x + 1.;
+    let double = |x: f64| 2. * x;
+    for m in 0..q {
+        let cts = sk.encrypt(m).eval_many(&[&succ, &double]);
+        let res: Vec<usize> = cts.iter().map(|ct| sk.decrypt(ct)).collect();
+        assert_eq!(res, vec![(m + 1) % q, 2 * m % q], "m = {}", m);
+    }
+}
+
+fn test_bool(i: usize, sk: &EncryptKey) -> usize {
+    use zqz::boolean::BoolCiphertext;
+
+    // generate random messages
+    let b1 = random_index!(2) == 1;
+    let b2 = random_index!(2) == 1;
+    let m3 = random_index!(PARAMS.modulo);
+
+    // encryption
+    let ct1 = sk.encrypt_bool(b1);
+    let ct2 = sk.encrypt_bool(b2);
+    let ct3 = sk.encrypt(m3);
+
+    // homomorphic evaluation
+    let ct_and = &ct1 & &ct2;
+    let ct_or = &ct1 | &ct2;
+    let ct_xor = &ct1 ^ &ct2;
+    let ct_not = !&ct1;
+    let ct_conv = BoolCiphertext::from_ciphertext(&ct3);
+
+    // an encrypted boolean whose message is only equal to b2 modulo q, after a translation
+    let translated = BoolCiphertext(&sk.encrypt(b2 as usize + 1) + (PARAMS.modulo - 1));
+    let ct_translated = &ct1 & &translated;
+
+    // decryption
+    let m = [
+        sk.decrypt_bool(&ct_and),
+        sk.decrypt_bool(&ct_or),
+        sk.decrypt_bool(&ct_xor),
+        sk.decrypt_bool(&ct_not),
+        sk.decrypt_bool(&ct_conv),
+        sk.decrypt_bool(&ct_translated),
+    ];
+
+    // test
+    let expected = [b1 & b2, b1 | b2, b1 ^ b2, !b1, m3 != 0, b1 & b2];
+    if expected != m {
+        println!(
+            "test_bool[{}]: [{1} & {2}, {1} | {2}, {1} ^ {2}, !{1}, {3} != 0, {1} & ({2} + 1 + q - 1)] = {4:?} != {5:?} (obtained after decryption)",
+            i, b1, b2, m3, expected, m
+        );
+        return 1;
+    }
+    0
+}
+
+#[test]
+fn test_key_header() {
+    use zqz::header::{
+        read_header, read_key_file, write_key_file, KeyEncoding, KeyHeader, KeyKind,
+    };
+    use zqz::keys::KeyError;
+
+    let path = std::env::temp_dir().join(format!("demo_z8z_header_{}.txt", std::process::id()));
+    let path = path.to_str().unwrap();
+    let header = KeyHeader::new(KeyKind::Bootstrapping, &PARAMS, 42, KeyEncoding::Text);
+    write_key_file(path, header.clone(), b"payload").unwrap();
+
+    // the header and the payload are read back
+    let (read, payload) = read_key_file(path, KeyKind::Bootstrapping, &PARAMS).unwrap();
+    assert_eq!(read.key_set_id, header.key_set_id);
+    assert_eq!(read.payload_size, 7);
+    assert_eq!(payload, b"payload");
+    assert_eq!(read_header(path).unwrap(), read);
+
+    // a key of another kind is rejected
+    match read_key_file(path, KeyKind::Secret, &PARAMS) {
+        Err(KeyError::WrongKind { .. }) => {}
+        res => panic!("unexpected result: {:?}", res),
+    }
+
+    // a truncated or corrupted file is rejected
+    let content = std::fs::read(path).unwrap();
+    std::fs::write(path, &content[..content.len() - 1]).unwrap();
+    match read_header(path) {
+        Err(KeyError::Corrupted { .. }) => {}
+        res => panic!("unexpected result: {:?}", res),
+    }
+    let mut corrupted = content.clone();
+    *corrupted.last_mut().unwrap() ^= 1;
+    std::fs::write(path, &corrupted).unwrap();
+    match read_key_file(path, KeyKind::Bootstrapping, &PARAMS) {
+        Err(KeyError::Corrupted { .. }) => {}
+        res => panic!("unexpected result: {:?}", res),
+    }
+
+    // a file without header is rejected
+    std::fs::write(path, "payload").unwrap();
+    match read_key_file(path, KeyKind::Bootstrapping, &PARAMS) {
+        Err(KeyError::MissingHeader(_)) => {}
+        res => panic!("unexpected result: {:?}", res),
+    }
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_key_store() {
+    use zqz::header::{write_key_file, KeyEncoding, KeyHeader, KeyKind};
+    use zqz::keys::{KeyError, KeySetState};
+
+    let dir = std::env::temp_dir().join(format!("demo_z8z_store_{}", std::process::id()));
+    let store = KeyStore::new(&dir).unwrap();
+    assert!(!store.contains(&PARAMS));
+
+    // a complete key set of the current parameters, written with fake payloads
+    let prefix = dir.join(PARAMS.gen_prefix()).to_string_lossy().into_owned();
+    for (file, kind) in [
+        ("secret_key.json", KeyKind::Secret),
+        ("bootstrapping_key.txt", KeyKind::Bootstrapping),
+        ("keyswitching_key.txt", KeyKind::Keyswitching),
+    ]
+    .iter()
+    {
+        let path = format!("{}_{}", prefix, file);
+        let header = KeyHeader::new(*kind, &PARAMS, 7, KeyEncoding::Text);
+        write_key_file(&path, header, b"key").unwrap();
+    }
+    assert!(store.contains(&PARAMS));
+
+    // an incomplete key set
+    let stale = dir.join("stale_secret_key.json");
+    std::fs::write(&stale, "key").unwrap();
+
+    let entries = store.list().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert!(entries.iter().any(|e| e.prefix == PARAMS.gen_prefix()
+        && e.status.as_ref().map(|s| s.1).ok() == Some(7)));
+
+    // only the incomplete key set is stale
+    assert_eq!(store.delete_stale(&[&PARAMS]).unwrap(), vec!["stale".to_string()]);
+    assert!(!stale.exists());
+    assert!(store.contains(&PARAMS));
+
+    // an incomplete key set of other parameters, e.g. left by an interrupted save, is reported
+    // when loaded, and replaced by a new one
+    let (_, other) = zqz::PRESETS.iter().find(|(_, p)| **p != PARAMS).unwrap();
+    let path = dir.join(format!("{}_secret_key.json", other.gen_prefix()));
+    let header = KeyHeader::new(KeyKind::Secret, other, 9, KeyEncoding::Text);
+    write_key_file(path.to_str().unwrap(), header, b"key").unwrap();
+    assert_eq!(store.state(other), KeySetState::Partial);
+    assert!(!store.contains(other));
+    assert!(matches!(store.load(other), Err(KeyError::PartialKeySet(_))));
+    let key = store.load_or_generate_with_params(other).unwrap();
+    assert_eq!(store.state(other), KeySetState::Complete);
+    assert!(store.load(other).unwrap() == key);
+
+    // a complete but unusable key set is not replaced
+    let content = std::fs::read(&path).unwrap();
+    let mut corrupted = content.clone();
+    *corrupted.last_mut().unwrap() ^= 1;
+    std::fs::write(&path, &corrupted).unwrap();
+    assert!(store.load_or_generate_with_params(other).is_err());
+    assert_eq!(std::fs::read(&path).unwrap(), corrupted);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_text_key_encoding() {
+    use zqz::header::KeyEncoding;
+
+    let dir = std::env::temp_dir().join(format!("demo_z8z_text_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let prefix = dir.join(PARAMS.gen_prefix()).to_string_lossy().into_owned();
+
+    // the keys are serialized in memory, so that no other file than the key files is written
+    let sk = test_key();
+    sk.save_to_files_with_encoding(&prefix, KeyEncoding::Text)
+        .unwrap();
+    let mut files: Vec<String> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    let mut expected = zqz::keys::key_files(&prefix);
+    expected.sort();
+    assert_eq!(files, expected);
+    assert!(EncryptKey::load_from_files(&prefix).unwrap() == sk);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_legacy_key_migration() {
+    let dir = std::env::temp_dir().join(format!("demo_z8z_legacy_{}", std::process::id()));
+    let store = KeyStore::new(&dir).unwrap();
+    let prefix = dir.join(PARAMS.gen_prefix()).to_string_lossy().into_owned();
+
+    // the previous versions wrote the keys with the functions of concrete, without header
+    let sk = test_key();
+    let (secret, keys) = sk.lwe_keys().unwrap();
+    let paths = zqz::keys::key_files(&prefix);
+    secret.save(&paths[0]).unwrap();
+    keys.bootstrapping.save(&paths[1]);
+    keys.keyswitching.save(&paths[2]);
+
+    // the keys are loaded, and saved again with headers
+    let loaded = store.load(&PARAMS).unwrap();
+    assert!(loaded.secret == sk.secret);
+    let header = zqz::keys::read_key_set_header(&prefix).unwrap();
+    assert_eq!(header.key_set_id, loaded.key_id());
+    assert!(store.load(&PARAMS).unwrap() == loaded);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Compares the time needed to save and load the keys with the different encodings. Run it with
+// `make bench-keys`.
+#[test]
+#[ignore]
+fn bench_key_serialization() {
+    use crate::measure_duration;
+    use colored::Colorize;
+    use zqz::header::KeyEncoding;
+
+    let sk = KeyStore::from_env()
+        .and_then(|store| store.load_or_generate())
+        .unwrap_or_else(|e| panic!("{}", e));
+    let dir = std::env::temp_dir().join(format!("demo_z8z_bench_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let prefix = dir.join(PARAMS.gen_prefix()).to_string_lossy().into_owned();
+
+    let mut encodings = vec![KeyEncoding::Text, KeyEncoding::Binary];
+    if cfg!(feature = "compression") {
+        encodings.push(KeyEncoding::CompressedBinary);
+    }
+    for encoding in encodings {
+        measure_duration!((format!("Saving ({:?})...", encoding)), [
+            sk.save_to_files_with_encoding(&prefix, encoding).unwrap();
+        ]);
+        let size: u64 = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().metadata().unwrap().len())
+            .sum();
+        println!("Size: {} bytes", size);
+        measure_duration!((format!("Loading ({:?})...", encoding)), [
+            let loaded = EncryptKey::load_from_files(&prefix).unwrap();
+        ]);
+        assert!(loaded == sk);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_seeded_secret_keys() {
+    use zqz::keys::seeded_secret_keys;
+
+    let (rlwe_sk1, lwe_sk1) = seeded_secret_keys(42, &PARAMS);
+    let (rlwe_sk2, lwe_sk2) = seeded_secret_keys(42, &PARAMS);
+    let (rlwe_sk3, _) = seeded_secret_keys(43, &PARAMS);
+    assert!(rlwe_sk1 == rlwe_sk2);
+    assert!(lwe_sk1 == lwe_sk2);
+    assert!(rlwe_sk1 != rlwe_sk3);
+}
+
+// Checks that the key sets derived from the same seed are interchangeable: their evaluation keys
+// are not bit-for-bit identical, since concrete draws their noise itself, but each one bootstraps
+// the ciphertexts of the other. Run it with `make test-seeded`, it generates two key sets.
+#[test]
+#[ignore]
+fn test_seeded_evaluation_keys() {
+    use zqz::ciphertext::Ciphertext;
+
+    let sk1 = EncryptKey::from_seed(42, &PARAMS);
+    let sk2 = EncryptKey::from_seed(42, &PARAMS);
+    assert_eq!(sk1.key_id(), sk2.key_id());
+    for m in 0..PARAMS.modulo {
+        // encrypted with the first key set, bootstrapped with the second one
+        let bytes = sk1.encrypt(m).to_bytes().unwrap();
+        let ct = Ciphertext::from_bytes(&bytes, sk2.evaluation_key()).unwrap();
+        let ct = ct.eval(|x| x + 1.);
+        assert_eq!(sk1.decrypt(&ct), (m + 1) % PARAMS.modulo, "m = {}", m);
+        assert_eq!(sk2.decrypt(&ct), (m + 1) % PARAMS.modulo, "m = {}", m);
+    }
+}
+
+#[test]
+fn test_secret_key_protection() {
+    use zqz::header::{write_key_file, KeyEncoding, KeyHeader, KeyKind};
+    use zqz::keys::{seeded_secret_keys, SecretKey};
+
+    // the secret key is redacted and compared by value
+    let (_, lwe_sk) = seeded_secret_keys(42, &PARAMS);
+    let (_, other_sk) = seeded_secret_keys(43, &PARAMS);
+    let secret = SecretKey(lwe_sk);
+    assert_eq!(format!("{:?}", secret), "SecretKey(<redacted>)");
+    assert!(secret == SecretKey(seeded_secret_keys(42, &PARAMS).1));
+    assert!(secret != SecretKey(other_sk));
+
+    // the secret key files are only accessible to their owner
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let path = "test_secret_key_protection.json";
+        let header = KeyHeader::new(KeyKind::Secret, &PARAMS, 42, KeyEncoding::Binary);
+        write_key_file(path, header, b"secret").unwrap();
+        let mode = std::fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn test_passphrase() {
+    use zqz::keys::KeyError;
+    use zqz::passphrase::{decrypt, encrypt, Passphrase};
+
+    let passphrase = Passphrase::new("correct horse battery staple");
+    let (encryption, encrypted) = encrypt("key", &passphrase, b"secret", b"header").unwrap();
+    assert_ne!(&encrypted[..], &b"secret"[..]);
+    assert_eq!(format!("{:?}", passphrase), "Passphrase(<redacted>)");
+
+    // the right passphrase decrypts the payload
+    let decrypted = decrypt("key", &passphrase, &encryption, &encrypted, b"header").unwrap();
+    assert_eq!(&decrypted[..], &b"secret"[..]);
+
+    // a wrong passphrase or a tampered header are detected
+    let wrong = Passphrase::new("wrong");
+    match decrypt("key", &wrong, &encryption, &encrypted, b"header") {
+        Err(KeyError::WrongPassphrase(_)) => {}
+        res => panic!("unexpected result: {:?}", res),
+    }
+    match decrypt("key", &passphrase, &encryption, &encrypted, b"tampered") {
+        Err(KeyError::WrongPassphrase(_)) => {}
+        res => panic!("unexpected result: {:?}", res),
+    }
+}
+
+fn test_public_encrypt(i: usize, sk: &EncryptKey, pk: &PublicKey) -> usize {
+    // generate random messages
+    let m1 = random_index!(PARAMS.modulo);
+    let m2 = random_index!(PARAMS.modulo);
+
+    // encryption with the public key
+    let ct1 = pk.encrypt(m1).unwrap();
+    let ct2 = sk.encrypt(m2);
+
+    // homomorphic evaluation
+    let ct3 = &ct1 + &ct2;
+
+    // decryption
+    let m_dec: usize = sk.decrypt(&ct1);
+    let m: usize = sk.decrypt(&ct3);
+
+    // test
+    if m1 != m_dec || (m1 + m2) % PARAMS.modulo != m {
+        println!(
+            "test_public_encrypt[{}]: {} + {} = {} != {} (obtained after decryption, {} for {})",
+            i,
+            m1,
+            m2,
+            (m1 + m2) % PARAMS.modulo,
+            m,
+            m_dec,
+            m1
+        );
+        return 1;
+    }
+    0
+}
+
+#[test]
+fn test_public_key_size() {
+    use zqz::keys::KeyError;
+
+    // (n + 1)·log2(q) + 2λ encryptions of zero, with q = 2^64 and λ = 128
+    for (_, params) in zqz::PRESETS.iter() {
+        assert_eq!(
+            PublicKey::min_size(params),
+            (params.lwe_setting.dimension + 1) * 64 + 256
+        );
+    }
+
+    let sk = test_key();
+    let min_size = PublicKey::min_size(&PARAMS);
+    match PublicKey::new(&sk, min_size - 1) {
+        Err(KeyError::InsecurePublicKey { size, .. }) => assert_eq!(size, min_size - 1),
+        res => panic!("unexpected result: {:?}", res.map(|pk| pk.size())),
+    }
+}
+
+// Encrypts with a public key of the minimal secure size, which takes a lot of time and memory. Run
+// it with `make test-public`.
+#[test]
+#[ignore]
+fn test_public_key() {
+    let sk = test_key();
+    let dir = std::env::temp_dir().join(format!("demo_z8z_public_{}", std::process::id()));
+    let store = KeyStore::new(&dir).unwrap();
+    store.save(&PARAMS, &sk).unwrap();
+    let pk = PublicKey::new(&sk, PublicKey::min_size(&PARAMS)).unwrap();
+    store.save_public_key(&pk).unwrap();
+
+    // the public key is loaded with its evaluation keys, without the secret key
+    let loaded = store.load_public_key(&PARAMS).unwrap();
+    assert!(loaded == pk);
+    assert_eq!(loaded.params(), &PARAMS);
+
+    let mut cpt: usize = 0;
+    for i in 0..100 {
+        cpt += test_public_encrypt(i, &sk, &loaded);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+    if cpt != 0 {
+        panic!("{} ERROR(S)!", cpt);
+    }
+}
+
+#[test]
+fn test_expr_parse() {
+    use zqz::expr::{parse, BinOp, Expr, ExprError, Statement};
+
+    let var = |name: &str| Box::new(Expr::Variable(name.to_string()));
+    assert_eq!(
+        parse("let x = a * b + 2; max(x, 3);").unwrap(),
+        vec![
+            Statement::Let(
+                "x".to_string(),
+                Expr::Binary(
+                    BinOp::Add,
+                    Box::new(Expr::Binary(BinOp::Mul, var("a"), var("b"))),
+                    Box::new(Expr::Constant(2))
+                )
+            ),
+            Statement::Expr(Expr::Call(
+                "max".to_string(),
+                vec![Expr::Variable("x".to_string()), Expr::Constant(3)]
+            )),
+        ]
+    );
+    assert_eq!(
+        parse("a - (b - c)").unwrap(),
+        vec![Statement::Expr(Expr::Binary(
+            BinOp::Sub,
+            var("a"),
+            Box::new(Expr::Binary(BinOp::Sub, var("b"), var("c")))
+        ))]
+    );
+    assert_eq!(parse(" ; ").unwrap(), vec![]);
+
+    for (source, position) in [("let = 2", 4), ("a +", 3), ("max(a b)", 6), ("a $ b", 2)].iter() {
+        match parse(source) {
+            Err(ExprError::Parse { position: p, .. }) => assert_eq!(p, *position, "{}", source),
+            res => panic!("unexpected result for `{}`: {:?}", source, res),
+        }
+    }
+}
+
+fn test_expr(i: usize, sk: &EncryptKey) -> usize {
+    use zqz::expr::{Env, Value};
+
+    // generate random messages
+    let m1 = random_index!(PARAMS.modulo);
+    let m2 = random_index!(PARAMS.modulo);
+    let m3 = random_index!(PARAMS.modulo);
+
+    // evaluation of the program
+    let source = format!(
+        "let a = enc({}); let b = enc({}); dec(max(a * b, {}) - a)",
+        m1, m2, m3
+    );
+    let res = Env::with_key(sk).run(&source);
+
+    // test
+    let expected = (PARAMS.modulo + usize::max(m1 * m2 % PARAMS.modulo, m3) - m1) % PARAMS.modulo;
+    if res != Ok(Some(Value::Clear(expected))) {
+        println!(
+            "test_expr[{}]: `{}` = {} != {:?} (obtained after decryption)",
+            i, source, expected, res
+        );
+        return 1;
+    }
+    0
+}
+
+#[test]
+fn test_expr_tables() {
+    use zqz::expr::{parse, Env, Expr, ExprError, Statement, Value};
+
+    assert_eq!(
+        parse("[1, 0](x)").unwrap(),
+        vec![Statement::Expr(Expr::Apply(
+            Box::new(Expr::Table(vec![1, 0])),
+            Box::new(Expr::Variable("x".to_string()))
+        ))]
+    );
+
+    // the tables are evaluated in the clear, without key
+    let q = PARAMS.modulo;
+    let square: Vec<String> = (0..q).map(|x| (x * x % q).to_string()).collect();
+    let succ: Vec<String> = (0..q).map(|x| ((x + 1) % q).to_string()).collect();
+    let mut env = Env::new(q);
+    let source = format!(
+        "let sq = [{}]; let f = sq([{}]); f(3) + [{}](1)",
+        square.join(", "),
+        succ.join(", "),
+        succ.join(", ")
+    );
+    assert_eq!(env.run(&source), Ok(Some(Value::Clear((16 + 2) % q))));
+
+    for source in ["[1, 2](3)", "let f = [1]; f + 1", "g(1)"].iter() {
+        match env.run(source) {
+            Err(ExprError::Eval(_)) => (),
+            res => panic!("unexpected result for `{}`: {:?}", source, res),
+        }
+    }
+    match env.run("enc(1)") {
+        Err(ExprError::Eval(_)) => (),
+        res => panic!("unexpected result for `enc(1)` without key: {:?}", res),
+    }
+}
+
+#[test]
+fn test_expr_compile() {
+    use zqz::circuit::Circuit;
+    use zqz::expr::{compile, Env, ExprError, Value};
+
+    // the compiled circuit computes the value of the program, its clear values being folded
+    let q = PARAMS.modulo;
+    let square: Vec<String> = (0..q).map(|x| (x * x % q).to_string()).collect();
+    let source = format!(
+        "let sq = [{}]; let c = 2 * 3; sq(x) - max(y, c) * 2 + 1",
+        square.join(", ")
+    );
+    let circuit = compile(&source, &["x", "y"], q).unwrap();
+    assert_eq!(circuit.nb_inputs(), 2);
+    assert_eq!(circuit.outputs().len(), 1);
+    for x in 0..q {
+        for y in 0..q {
+            let mut env = Env::new(q);
+            env.set("x", Value::Clear(x));
+            env.set("y", Value::Clear(y));
+            let expected = match env.run(&source) {
+                Ok(Some(Value::Clear(m))) => m,
+                res => panic!("unexpected result: {:?}", res),
+            };
+            assert_eq!(circuit.run_clear(&[x, y]), vec![expected]);
+        }
+    }
+    assert_eq!(
+        Circuit::from_bytes(&circuit.to_bytes()).as_ref(),
+        Ok(&circuit)
+    );
+
+    // the programs which need the secret key, or whose result is not encrypted, are rejected
+    for source in ["enc(1) + x", "dec(x)", "1 + 2", "[1, 0]", "z + 1", ""].iter() {
+        match compile(source, &["x"], q) {
+            Err(ExprError::Eval(_)) => (),
+            res => panic!("unexpected result for `{}`: {:?}", source, res),
+        }
+    }
+}
+
+fn test_expr_program(i: usize, sk: &EncryptKey) -> usize {
+    use zqz::expr::{eval_program, Value};
+
+    // generate random messages and a random table
+    let m1 = random_index!(PARAMS.modulo);
+    let m2 = random_index!(PARAMS.modulo);
+    let table: Vec<usize> = (0..PARAMS.modulo)
+        .map(|_| random_index!(PARAMS.modulo))
+        .collect();
+    let outputs: Vec<String> = table.iter().map(|x| x.to_string()).collect();
+
+    // evaluation of the program over the encrypted inputs, without the secret key
+    let source = format!("let f = [{}]; f(x) - y", outputs.join(", "));
+    let res = match eval_program(&source, &[("x", sk.encrypt(m1)), ("y", sk.encrypt(m2))]) {
+        Ok(Some(Value::Encrypted(ct))) => sk.decrypt(&ct),
+        res => {
+            println!("test_expr_program[{}]: `{}` gave {:?}", i, source, res);
+            return 1;
+        }
     };
 
+    // test
+    let expected = (table[m1] + PARAMS.modulo - m2) % PARAMS.modulo;
+    if res != expected {
+        println!(
+            "test_expr_program[{}]: `{}` with x = {}, y = {}: {} != {} (obtained after decryption)",
+            i, source, m1, m2, expected, res
+        );
+        return 1;
+    }
+    0
+}
+
+// Evaluates a real function in a circuit, as `Ciphertext::eval` does on a ciphertext.
+fn circuit_eval<F: Fn(f64) -> f64>(
+    circuit: &mut zqz::circuit::Circuit,
+    a: zqz::circuit::NodeId,
+    f: F,
+) -> zqz::circuit::NodeId {
+    let lut = zqz::lut::LookupTable::from_f64_fn(circuit.modulo(), f);
+    circuit.apply(a, &lut)
+}
+
+// Builds the circuit of the demo, max(((a * b + c) + 2) * 3, f(a)) with f(x) = x^2, with three
+// inputs and two outputs.
+fn demo_circuit() -> zqz::circuit::Circuit {
+    let mut circuit = zqz::circuit::Circuit::new();
+    let a = circuit.input();
+    let b = circuit.input();
+    let c = circuit.input();
+    let two = circuit.constant(2);
+    let three = circuit.constant(3);
+    let ab = circuit.mul(a, b);
+    let res = circuit.add(ab, c);
+    let res = circuit.add(res, two);
+    let res = circuit.mul(res, three);
+    let square = circuit_eval(&mut circuit, a, |x| x * x);
+    let max = circuit.max(res, square);
+    circuit.output(res);
+    circuit.output(max);
+    circuit
+}
+
+#[test]
+fn test_circuit_clear() {
+    let q = PARAMS.modulo;
+    let circuit = demo_circuit();
+    assert_eq!(circuit.nb_inputs(), 3);
+    for (a, b, c) in [(4, 7, 5), (0, 0, 0), (1, 2, 3)].iter() {
+        let res = ((a * b + c) + 2) * 3 % q;
+        assert_eq!(
+            circuit.run_clear(&[*a, *b, *c]),
+            vec![res, usize::max(res, a * a % q)]
+        );
+    }
+
+    // the nodes no output depends on are not executed
+    let mut circuit = zqz::circuit::Circuit::new();
+    let a = circuit.input();
+    let b = circuit.input();
+    circuit.mul(a, b);
+    let sum = circuit.add(a, b);
+    circuit.output(sum);
+    assert_eq!(circuit.live_nodes(), vec![true, true, false, true]);
+    assert_eq!(circuit.run_clear(&[1, 2]), vec![3]);
+}
+
+fn test_circuit(i: usize, sk: &EncryptKey) -> usize {
+    // generate random messages
+    let m1 = random_index!(PARAMS.modulo);
+    let m2 = random_index!(PARAMS.modulo);
+    let m3 = random_index!(PARAMS.modulo);
+
+    // execution of the circuit
+    let circuit = demo_circuit();
+    let inputs = [sk.encrypt(m1), sk.encrypt(m2), sk.encrypt(m3)];
+    let res: Vec<usize> = circuit
+        .run(&inputs)
+        .unwrap()
+        .iter()
+        .map(|ct| sk.decrypt(ct))
+        .collect();
+
+    // test
+    let expected = circuit.run_clear(&[m1, m2, m3]);
+    if res != expected {
+        println!(
+            "test_circuit[{}]: inputs ({}, {}, {}): {:?} != {:?} (obtained after decryption)",
+            i, m1, m2, m3, expected, res
+        );
+        return 1;
+    }
+    0
+}
+
+#[test]
+fn test_circuit_optimizer() {
+    use zqz::circuit::{Circuit, Node};
+    use zqz::optimizer::optimize;
+
+    // the optimized circuits compute the same outputs, for all the inputs
+    let q = PARAMS.modulo;
+    let circuit = demo_circuit();
+    let (optimized, report) = optimize(&circuit);
+    for m in 0..q * q * q {
+        let inputs = [m % q, m / q % q, m / q / q];
+        assert_eq!(optimized.run_clear(&inputs), circuit.run_clear(&inputs));
+    }
+    assert_eq!(report.bootstraps_before, circuit.bootstrap_cost());
+    assert_eq!(report.bootstraps_after, optimized.bootstrap_cost());
+    assert!(report.bootstraps_saved() > 0, "{}", report);
+
+    // consecutive lookups and operations with constants are fused into one lookup
+    let mut circuit = Circuit::new();
+    let a = circuit.input();
+    let two = circuit.constant(2);
+    let three = circuit.constant(3);
+    let res = circuit_eval(&mut circuit, a, |x| x * x);
+    let res = circuit_eval(&mut circuit, res, |x| x + 1.);
+    let res = circuit.add(res, two);
+    let res = circuit.mul(res, three);
+    circuit.output(res);
+    let (optimized, report) = optimize(&circuit);
+    assert_eq!(report.bootstraps_before, 3);
+    assert_eq!(report.bootstraps_after, 1);
+    assert_eq!(optimized.nodes().len(), 2);
+    for m in 0..q {
+        assert_eq!(optimized.run_clear(&[m]), vec![(m * m + 3) * 3 % q]);
+    }
+
+    // the lookup following an addition is applied by its modulo bootstrap, and a shared value is
+    // only computed once
+    let mut circuit = Circuit::new();
+    let a = circuit.input();
+    let b = circuit.input();
+    let sum = circuit.add(a, b);
+    let square = circuit_eval(&mut circuit, sum, |x| x * x);
+    let cube = circuit_eval(&mut circuit, square, |x| x * x * x);
+    circuit.output(square);
+    circuit.output(cube);
+    let (optimized, report) = optimize(&circuit);
+    assert_eq!(report.bootstraps_saved(), 1);
+    assert!(matches!(optimized.nodes()[2], Node::AddApply(..)));
+
+    // the operations on constants are folded, and only the additions of constants are not
+    // bootstrapped
+    let mut circuit = Circuit::new();
+    let a = circuit.input();
+    let two = circuit.constant(2);
+    let four = circuit.add(two, two);
+    let res = circuit.add(a, four);
+    let res = circuit.sub(res, two);
+    circuit.output(res);
+    let (optimized, report) = optimize(&circuit);
+    assert_eq!(report.bootstraps_after, 0);
+    assert_eq!(optimized.run_clear(&[1]), vec![3 % q]);
+}
+
+#[test]
+fn test_circuit_serialization() {
+    use zqz::circuit::{Circuit, CircuitError, CIRCUIT_VERSION};
+    use zqz::optimizer::optimize;
+
+    for circuit in [demo_circuit(), optimize(&demo_circuit()).0].iter() {
+        let bytes = circuit.to_bytes();
+        let header = Circuit::read_header(&bytes).unwrap();
+        assert_eq!(header.version, CIRCUIT_VERSION);
+        assert_eq!(header.modulo, PARAMS.modulo);
+        assert_eq!(header.min_padding, 2);
+        assert_eq!((header.nb_inputs, header.nb_outputs), (3, 2));
+        assert_eq!(Circuit::from_bytes(&bytes).as_ref(), Ok(circuit));
+    }
+
+    // truncated or foreign bytes
+    let bytes = demo_circuit().to_bytes();
+    match Circuit::from_bytes(&bytes[..bytes.len() - 1]) {
+        Err(CircuitError::InvalidFormat(_)) => {}
+        res => panic!("unexpected result: {:?}", res),
+    }
+    match Circuit::read_header(b"not a circuit") {
+        Err(CircuitError::InvalidFormat(_)) => {}
+        res => panic!("unexpected result: {:?}", res),
+    }
+
+    // a circuit of another modulo, whose lookup table is corrupted: its last output is followed
+    // by the list of the outputs, made of a length and an index of 8 bytes each
+    let mut circuit = Circuit::with_modulo(2 * PARAMS.modulo);
+    let a = circuit.input();
+    let res = circuit_eval(&mut circuit, a, |x| x + 1.);
+    circuit.output(res);
+    assert_eq!(circuit.min_padding(), 1);
+    let mut bytes = circuit.to_bytes();
+    let last = bytes.len() - 24;
+    bytes[last] = 255;
+    match Circuit::from_bytes(&bytes) {
+        Err(CircuitError::InvalidNode { index: 1, .. }) => {}
+        res => panic!("unexpected result: {:?}", res),
+    }
+
+    // the parameters of the key set
+    assert_eq!(demo_circuit().check_params(&PARAMS), Ok(()));
+    match circuit.check_params(&PARAMS) {
+        Err(CircuitError::UnsupportedParameters(_)) => {}
+        res => panic!("unexpected result: {:?}", res),
+    }
+}
+
+#[test]
+fn test_circuit_other_params() {
+    use zqz::circuit::CircuitError;
+
+    // a circuit over the Z/qZ of the build is not run on ciphertexts of another modulo
+    let sk = KeyStore::from_env()
+        .and_then(|store| store.load_or_generate_with_params(other_params()))
+        .unwrap_or_else(|e| panic!("{}", e));
+    let inputs = [sk.encrypt(1), sk.encrypt(2), sk.encrypt(3)];
+    match demo_circuit().run(&inputs) {
+        Err(CircuitError::UnsupportedParameters(_)) => {}
+        res => panic!("unexpected result: {:?}", res.map(|outputs| outputs.len())),
+    }
+}
+
+fn test_circuit_optimized(i: usize, sk: &EncryptKey) -> usize {
+    use zqz::ciphertext::bootstrap_count;
+    use zqz::optimizer::optimize;
+
+    // generate random messages
+    let m1 = random_index!(PARAMS.modulo);
+    let m2 = random_index!(PARAMS.modulo);
+    let m3 = random_index!(PARAMS.modulo);
+
+    // execution of the optimized circuit
+    let (circuit, report) = optimize(&demo_circuit());
+    let inputs = [sk.encrypt(m1), sk.encrypt(m2), sk.encrypt(m3)];
+    let before = bootstrap_count();
+    let outputs = circuit.run(&inputs).unwrap();
+    let bootstraps = bootstrap_count() - before;
+    let res: Vec<usize> = outputs.iter().map(|ct| sk.decrypt(ct)).collect();
+
+    // test
+    let expected = demo_circuit().run_clear(&[m1, m2, m3]);
+    if res != expected || bootstraps != report.bootstraps_after {
+        println!(
+            "test_circuit_optimized[{}]: inputs ({}, {}, {}): {:?} != {:?} (obtained after \
+             decryption) with {} bootstrap(s) instead of {}",
+            i, m1, m2, m3, expected, res, bootstraps, report.bootstraps_after
+        );
+        return 1;
+    }
+    0
+}
+
+#[test]
+fn test_ciphertext_serialization() {
+    use zqz::ciphertext::{Ciphertext, CiphertextError, CIPHERTEXT_VERSION};
+    use zqz::header::ParametersHeader;
+
+    let sk = EncryptKey::new_zero();
+    let ct = sk.encrypt(3);
+    let bytes = ct.to_bytes().unwrap();
+
+    let header = Ciphertext::read_header(&bytes).unwrap();
+    assert_eq!(header.version, CIPHERTEXT_VERSION);
+    assert_eq!(header.key_set_id, sk.key_id());
+    assert_eq!(header.parameters, ParametersHeader::from(&PARAMS));
+    assert_eq!(
+        Ciphertext::from_bytes(&bytes, sk.evaluation_key()).unwrap(),
+        ct
+    );
+
+    // another key set
+    let other = EncryptKey::new_zero();
+    assert_eq!(
+        Ciphertext::from_bytes(&bytes, other.evaluation_key()),
+        Err(CiphertextError::KeySetMismatch {
+            expected: other.key_id(),
+            found: sk.key_id()
+        })
+    );
+
+    // truncated or foreign bytes
+    match Ciphertext::from_bytes(&bytes[..bytes.len() - 1], sk.evaluation_key()) {
+        Err(CiphertextError::InvalidFormat(_)) => {}
+        res => panic!("unexpected result: {:?}", res),
+    }
+    match Ciphertext::read_header(b"not a ciphertext") {
+        Err(CiphertextError::InvalidFormat(_)) => {}
+        res => panic!("unexpected result: {:?}", res),
+    }
+}
+
+#[test]
+#[should_panic(expected = "different key sets")]
+fn test_key_mismatch() {
+    let sk1 = EncryptKey::new_zero();
+    let sk2 = EncryptKey::new_zero();
+    assert_ne!(sk1.key_id(), sk2.key_id());
+
+    let ct1 = sk1.encrypt(1);
+    let ct2 = sk2.encrypt(2);
+    let _ = &ct1 + &ct2;
+}
+
+// Returns the parameters the test ciphertexts are converted to and from: Z/16Z for the Z/8Z
+// parameters, and Z/8Z for the Z/16Z ones.
+fn other_params() -> &'static zqz::Parameters {
+    if PARAMS.modulo == zqz::Z16Z_KS.modulo {
+        &zqz::Z8Z_KS
+    } else {
+        &zqz::Z16Z_KS
+    }
+}
+
+fn test_convert(
+    i: usize,
+    sk: &EncryptKey,
+    other_sk: &EncryptKey,
+    to_other: &ConversionKey,
+    from_other: &ConversionKey,
+) -> usize {
+    // generate random messages
+    let m1 = random_index!(PARAMS.modulo);
+    let m2 = random_index!(other_sk.params().modulo);
+
+    // encryption
+    let ct1 = sk.encrypt(m1);
+    let ct2 = other_sk.encrypt(m2);
+
+    // conversion
+    let ct1_other = ct1.convert(to_other).unwrap();
+    let ct2_back = ct2.convert(from_other).unwrap();
+
+    // decryption
+    let m1_dec: usize = other_sk.decrypt(&ct1_other);
+    let m2_dec: usize = sk.decrypt(&ct2_back);
+
+    // test
+    let expected = (m1 % other_sk.params().modulo, m2 % PARAMS.modulo);
+    if expected != (m1_dec, m2_dec) {
+        println!(
+            "test_convert[{}]: ({}, {}) converted to {:?} != {:?} (obtained after decryption)",
+            i,
+            m1,
+            m2,
+            expected,
+            (m1_dec, m2_dec)
+        );
+        return 1;
+    }
+    0
+}
+
+#[test]
+fn test_conversion() {
+    use zqz::ciphertext::CiphertextError;
+
+    let sk = test_key();
+    let other_sk = KeyStore::from_env()
+        .and_then(|store| store.load_or_generate_with_params(other_params()))
+        .unwrap_or_else(|e| panic!("{}", e));
+    let to_other = ConversionKey::new(&sk, &other_sk).unwrap();
+    let from_other = ConversionKey::new(&other_sk, &sk).unwrap();
+    assert_eq!(to_other.target_id(), other_sk.key_id());
+    assert_eq!(from_other.source_id(), other_sk.key_id());
+
+    // a ciphertext of another key set is rejected
+    match other_sk.encrypt(1).convert(&to_other) {
+        Err(CiphertextError::KeySetMismatch { expected, found }) => {
+            assert_eq!((expected, found), (sk.key_id(), other_sk.key_id()))
+        }
+        res => panic!("unexpected result: {:?}", res.map(|_| ())),
+    }
+
+    let mut cpt: usize = 0;
+    for i in 0..100 {
+        cpt += test_convert(i, &sk, &other_sk, &to_other, &from_other);
+    }
+    if cpt != 0 {
+        panic!("{} ERROR(S)!", cpt);
+    }
+}
+
+// Applies the operation `k` of the differential tests to two ciphertexts and a constant.
+fn backend_step(
+    k: usize,
+    x: &zqz::ciphertext::Ciphertext,
+    y: &zqz::ciphertext::Ciphertext,
+    c: usize,
+) -> zqz::ciphertext::Ciphertext {
+    use zqz::lut::LookupTable;
+    use zqz::max::max;
+
+    match k {
+        0 => x + y,
+        1 => x - y,
+        2 => x * y,
+        3 => max(x, y),
+        4 => x + c,
+        5 => x * c,
+        6 => max(x, c),
+        7 => x.apply(&LookupTable::from_fn(PARAMS.modulo, |m| m * m + c)),
+        _ => zqz::linear::dot(&[x.clone(), y.clone()], &[c, 1]),
+    }
+}
+
+#[test]
+fn test_plaintext_backend() {
+    use zqz::backend::Backend;
+    use zqz::ciphertext::{Ciphertext, CiphertextError};
+    use zqz::keys::KeyError;
+    use zqz::max::max;
+
+    let q = PARAMS.modulo;
+    let sk = EncryptKey::plaintext(&PARAMS);
+    assert_eq!(sk.backend(), Backend::Plaintext);
+    assert_eq!(EncryptKey::new_zero().backend(), Backend::Lwe);
+
+    // every operator on every pair of messages
+    for m1 in 0..q {
+        for m2 in 0..q {
+            let ct1 = sk.encrypt(m1);
+            let ct2 = sk.encrypt(m2);
+            assert_eq!(sk.decrypt(&(&ct1 + &ct2)), (m1 + m2) % q);
+            assert_eq!(sk.decrypt(&(&ct1 - &ct2)), (m1 + q - m2) % q);
+            assert_eq!(sk.decrypt(&(&ct1 * &ct2)), m1 * m2 % q);
+            assert_eq!(sk.decrypt(&max(&ct1, &ct2)), usize::max(m1, m2));
+            assert_eq!(sk.decrypt(&(&ct1 + m2)), (m1 + m2) % q);
+            assert_eq!(sk.decrypt(&(&ct1 - m2)), (m1 + q - m2) % q);
+            assert_eq!(sk.decrypt(&(&ct1 * m2)), m1 * m2 % q);
+            assert_eq!(sk.decrypt(&max(&ct1, m2)), usize::max(m1, m2));
+            assert_eq!(sk.decrypt(&ct1.eval_poly(&[m2, 1])), (m1 + m2) % q);
+            let ct3 = zqz::linear::dot(&[ct1, ct2], &[2, 3]);
+            assert_eq!(sk.decrypt(&ct3), (2 * m1 + 3 * m2) % q);
+        }
+    }
+
+    // the plaintext ciphertexts are not meant to be stored
+    let lwe_sk = EncryptKey::new_zero();
+    let bytes = lwe_sk.encrypt(1).to_bytes().unwrap();
+    match Ciphertext::from_bytes(&bytes, sk.evaluation_key()) {
+        Err(CiphertextError::PlaintextBackend) => (),
+        res => panic!("unexpected result: {:?}", res),
+    }
+    match sk.encrypt(1).to_bytes() {
+        Err(CiphertextError::PlaintextBackend) => (),
+        res => panic!("unexpected result: {:?}", res),
+    }
+
+    // nor are the key sets, which have no LWE keys to convert or encrypt with
+    let dir = std::env::temp_dir().join(format!("demo_z8z_plaintext_{}", std::process::id()));
+    let store = KeyStore::new(&dir).unwrap();
+    let min_size = PublicKey::min_size(&PARAMS);
+    assert!(matches!(
+        store.save(&PARAMS, &sk),
+        Err(KeyError::PlaintextBackend)
+    ));
+    assert!(!store.contains(&PARAMS));
+    assert!(matches!(
+        ConversionKey::new(&sk, &lwe_sk),
+        Err(KeyError::PlaintextBackend)
+    ));
+    assert!(matches!(
+        PublicKey::new(&sk, min_size),
+        Err(KeyError::PlaintextBackend)
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+fn test_plaintext_differential(i: usize, sk: &EncryptKey) -> usize {
+    use zqz::ciphertext::bootstrap_count;
+
+    // the same random operations on both backends
+    let plain = EncryptKey::plaintext(sk.params());
+    let q = PARAMS.modulo;
+    let (m1, m2) = (random_index!(q), random_index!(q));
+    let (mut x, mut y) = (sk.encrypt(m1), sk.encrypt(m2));
+    let (mut px, mut py) = (plain.encrypt(m1), plain.encrypt(m2));
+    for step in 0..3 {
+        let (k, c) = (random_index!(9), random_index!(q));
+        let before = bootstrap_count();
+        let z = backend_step(k, &x, &y, c);
+        let bootstraps = bootstrap_count() - before;
+        let before = bootstrap_count();
+        let pz = backend_step(k, &px, &py, c);
+        let plain_bootstraps = bootstrap_count() - before;
+
+        // test
+        let (m, pm) = (sk.decrypt(&z), plain.decrypt(&pz));
+        if m != pm || bootstraps != plain_bootstraps {
+            println!(
+                "test_plaintext_differential[{}]: operation {} (constant {}) at step {} of ({}, {}): \
+                 {} with {} bootstrap(s) != {} with {} bootstrap(s) (obtained after decryption)",
+                i,
+                k,
+                c,
+                step,
+                m1,
+                m2,
+                pm,
+                plain_bootstraps,
+                m,
+                bootstraps
+            );
+            return 1;
+        }
+        x = y;
+        y = z;
+        px = py;
+        py = pz;
+    }
+    0
+}
+
+// Encrypts the messages and evaluates an operator on them, which may have several outputs.
+type EncryptedOperator = dyn Fn(&EncryptKey, usize, usize) -> Vec<zqz::ciphertext::Ciphertext>;
+
+// An operator of the exhaustive tests, evaluated on messages of its domain, Z/qZ or the booleans:
+// the first one is encrypted, and the second one is encrypted or not depending on the operator,
+// and ignored by the unary ones.
+struct Operator {
+    name: String,
+    arity: usize,
+    domain: usize,
+    clear: Box<dyn Fn(usize, usize) -> Vec<usize>>,
+    encrypted: Box<EncryptedOperator>,
+}
+
+impl Operator {
+    fn new<C, E>(name: &str, arity: usize, clear: C, encrypted: E) -> Operator
+    where
+        C: Fn(usize, usize) -> usize + 'static,
+        E: Fn(&EncryptKey, usize, usize) -> zqz::ciphertext::Ciphertext + 'static,
+    {
+        Operator::with_outputs(
+            name,
+            arity,
+            move |a, b| vec![clear(a, b)],
+            move |sk, a, b| vec![encrypted(sk, a, b)],
+        )
+    }
+
+    // An operator with several outputs.
+    fn with_outputs<C, E>(name: &str, arity: usize, clear: C, encrypted: E) -> Operator
+    where
+        C: Fn(usize, usize) -> Vec<usize> + 'static,
+        E: Fn(&EncryptKey, usize, usize) -> Vec<zqz::ciphertext::Ciphertext> + 'static,
+    {
+        Operator {
+            name: name.to_string(),
+            arity,
+            domain: PARAMS.modulo,
+            clear: Box::new(clear),
+            encrypted: Box::new(encrypted),
+        }
+    }
+
+    // An operator on encrypted booleans, whose messages are 0 and 1.
+    fn boolean<C, E>(name: &str, arity: usize, clear: C, encrypted: E) -> Operator
+    where
+        C: Fn(bool, bool) -> bool + 'static,
+        E: Fn(
+                &zqz::boolean::BoolCiphertext,
+                &zqz::boolean::BoolCiphertext,
+            ) -> zqz::boolean::BoolCiphertext
+            + 'static,
+    {
+        let mut operator = Operator::new(
+            name,
+            arity,
+            move |a, b| clear(a != 0, b != 0) as usize,
+            move |sk, a, b| {
+                let res = encrypted(&sk.encrypt_bool(a != 0), &sk.encrypt_bool(b != 0));
+                res.into_ciphertext()
+            },
+        );
+        operator.domain = 2;
+        operator
+    }
+}
+
+// Returns the operators of the exhaustive tests: every operator between two ciphertexts or a
+// ciphertext and a constant, the evaluations of functions, polynomials, lookup tables and linear
+// combinations, and the operators on encrypted booleans.
+fn exhaustive_operators() -> Vec<Operator> {
+    use zqz::boolean::BoolCiphertext;
+    use zqz::ciphertext::Ciphertext;
+    use zqz::lut::LookupTable;
+    use zqz::max::max;
+
+    let q = PARAMS.modulo;
+    let mut operators = vec![
+        Operator::new(
+            "add",
+            2,
+            move |a, b| (a + b) % q,
+            |sk, a, b| &sk.encrypt(a) + &sk.encrypt(b),
+        ),
+        Operator::new(
+            "sub",
+            2,
+            move |a, b| (a + q - b) % q,
+            |sk, a, b| &sk.encrypt(a) - &sk.encrypt(b),
+        ),
+        Operator::new(
+            "mul",
+            2,
+            move |a, b| a * b % q,
+            |sk, a, b| &sk.encrypt(a) * &sk.encrypt(b),
+        ),
+        Operator::new("max", 2, usize::max, |sk, a, b| {
+            max(&sk.encrypt(a), &sk.encrypt(b))
+        }),
+        Operator::new(
+            "add_cst",
+            2,
+            move |a, b| (a + b) % q,
+            |sk, a, b| &sk.encrypt(a) + b,
+        ),
+        Operator::new(
+            "sub_cst",
+            2,
+            move |a, b| (a + q - b) % q,
+            |sk, a, b| &sk.encrypt(a) - b,
+        ),
+        Operator::new(
+            "mul_cst",
+            2,
+            move |a, b| a * b % q,
+            |sk, a, b| &sk.encrypt(a) * b,
+        ),
+        Operator::new("max_cst", 2, usize::max, |sk, a, b| max(&sk.encrypt(a), b)),
+        Operator::new("max_cst_rev", 2, usize::max, |sk, a, b| {
+            max(b, &sk.encrypt(a))
+        }),
+        Operator::new(
+            "add_apply",
+            2,
+            move |a, b| (a + b) * (a + b) % q,
+            |sk, a, b| {
+                let square = LookupTable::from_fn(sk.params().modulo, |x| x * x);
+                sk.encrypt(a).add_apply(&sk.encrypt(b), &square)
+            },
+        ),
+        Operator::new(
+            "sub_apply",
+            2,
+            move |a, b| (a + q - b) % q / 2,
+            |sk, a, b| {
+                let half = LookupTable::from_fn(sk.params().modulo, |x| x / 2);
+                sk.encrypt(a).sub_apply(&sk.encrypt(b), &half)
+            },
+        ),
+    ];
+
+    // evaluations of functions and polynomials
+    let qf = q as f64;
+    operators.push(Operator::new(
+        "eval",
+        1,
+        move |a, _| (a * a + 1) % q,
+        move |sk, a, _| sk.encrypt(a).eval(move |x| (x * x + 1.) % qf),
+    ));
+    operators.push(Operator::new(
+        "eval_poly",
+        1,
+        move |a, _| (1 + 2 * a + 3 * a * a) % q,
+        |sk, a, _| sk.encrypt(a).eval_poly(&[1, 2, 3]),
+    ));
+    operators.push(Operator::new(
+        "eval_poly_sum",
+        2,
+        move |a, b| (1 + 2 * a + 3 * b * b) % q,
+        |sk, a, b| {
+            let (ct_a, ct_b) = (sk.encrypt(a), sk.encrypt(b));
+            Ciphertext::eval_poly_sum(&[(&ct_a, &[1, 2][..]), (&ct_b, &[0, 0, 3][..])])
+        },
+    ));
+    operators.push(Operator::with_outputs(
+        "apply_many",
+        1,
+        move |a, _| vec![a * a % q, a / 2, a * a % q],
+        |sk, a, _| {
+            let square = LookupTable::from_fn(sk.params().modulo, |x| x * x);
+            let half = LookupTable::from_fn(sk.params().modulo, |x| x / 2);
+            sk.encrypt(a).apply_many(&[square.clone(), half, square])
+        },
+    ));
+    operators.push(Operator::with_outputs(
+        "eval_many",
+        1,
+        move |a, _| vec![(a + 1) % q, (q - a) % q],
+        move |sk, a, _| {
+            let succ = move |x: f64| (x + 1.) % qf;
+            let neg = move |x: f64| (qf - x) % qf;
+            sk.encrypt(a).eval_many(&[&succ, &neg])
+        },
+    ));
+
+    // linear combinations
+    operators.push(Operator::new(
+        "dot",
+        2,
+        move |a, b| (2 * a + 3 * b) % q,
+        |sk, a, b| zqz::linear::dot(&[sk.encrypt(a), sk.encrypt(b)], &[2, 3]),
+    ));
+    operators.push(Operator::with_outputs(
+        "matvec",
+        2,
+        move |a, b| vec![(a + b) % q, (2 * a + 3 * b) % q],
+        |sk, a, b| {
+            let matrix = [vec![1, 1], vec![2, 3]];
+            zqz::linear::matvec(&matrix, &[sk.encrypt(a), sk.encrypt(b)])
+        },
+    ));
+
+    // encrypted booleans
+    operators.push(Operator::new(
+        "bool_from_ciphertext",
+        1,
+        |a, _| (a != 0) as usize,
+        |sk, a, _| BoolCiphertext::from_ciphertext(&sk.encrypt(a)).into_ciphertext(),
+    ));
+    operators.push(Operator::boolean("bool_and", 2, |a, b| a & b, |a, b| a & b));
+    operators.push(Operator::boolean("bool_or", 2, |a, b| a | b, |a, b| a | b));
+    operators.push(Operator::boolean("bool_xor", 2, |a, b| a ^ b, |a, b| a ^ b));
+    operators.push(Operator::boolean("bool_not", 1, |a, _| !a, |a, _| !a));
+
+    // lookup tables, including a random one
+    let random: Vec<usize> = (0..q).map(|_| random_index!(q)).collect();
+    let tables = vec![
+        ("identity", (0..q).collect::<Vec<usize>>()),
+        ("square", (0..q).map(|x| x * x % q).collect()),
+        ("negation", (0..q).map(|x| (q - x) % q).collect()),
+        ("half", (0..q).map(|x| x / 2).collect()),
+        ("random", random),
+    ];
+    for (name, table) in tables.into_iter() {
+        let lut = LookupTable::new(&table, q).unwrap();
+        operators.push(Operator::new(
+            &format!("lookup({})", name),
+            1,
+            move |a, _| table[a],
+            move |sk, a, _| sk.encrypt(a).apply(&lut),
+        ));
+    }
+    operators
+}
+
+// The failures of an operator over its whole domain: the number of failed runs of each input, or
+// pair of inputs in row-major order. A run fails when one of the outputs is decrypted wrongly.
+struct FailureMatrix {
+    name: String,
+    arity: usize,
+    domain: usize,
+    runs: usize,
+    failures: Vec<usize>,
+}
+
+impl FailureMatrix {
+    // Runs the operator on every input, or pair of inputs, and compares the decrypted results
+    // with the ones computed in the clear.
+    fn new(sk: &EncryptKey, operator: &Operator, runs: usize) -> FailureMatrix {
+        let d = operator.domain;
+        let size = if operator.arity == 1 { d } else { d * d };
+        let failures = (0..size)
+            .map(|k| {
+                let (a, b) = if operator.arity == 1 {
+                    (k, 0)
+                } else {
+                    (k / d, k % d)
+                };
+                let expected = (operator.clear)(a, b);
+                (0..runs)
+                    .filter(|_| {
+                        let res = (operator.encrypted)(sk, a, b);
+                        res.iter().map(|ct| sk.decrypt(ct)).collect::<Vec<usize>>() != expected
+                    })
+                    .count()
+            })
+            .collect();
+        FailureMatrix {
+            name: operator.name.clone(),
+            arity: operator.arity,
+            domain: d,
+            runs,
+            failures,
+        }
+    }
+
+    // Returns the total number of failed runs.
+    fn total(&self) -> usize {
+        self.failures.iter().sum()
+    }
+
+    // Returns the total number of runs.
+    fn total_runs(&self) -> usize {
+        self.failures.len() * self.runs
+    }
+}
+
+// Prints the number of failures, and the matrix of the failures if there are some: a dot stands
+// for an input without failure.
+impl std::fmt::Display for FailureMatrix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use zqz::estimator::{wilson_interval, Z_95};
+
+        let q = self.domain;
+        let (low, high) = wilson_interval(self.total(), self.total_runs(), Z_95);
+        writeln!(
+            f,
+            "{}: {} failure(s) over {} input(s) x {} run(s), rate {:.2e}, 95% CI [{:.2e}, {:.2e}]",
+            self.name,
+            self.total(),
+            self.failures.len(),
+            self.runs,
+            self.total() as f64 / self.total_runs() as f64,
+            low,
+            high
+        )?;
+        if self.total() == 0 {
+            return Ok(());
+        }
+        let width = usize::max(3, self.runs.to_string().len() + 1);
+        let cell = |failures: usize| match failures {
+            0 => ".".to_string(),
+            n => n.to_string(),
+        };
+        if self.arity == 1 {
+            write!(f, "{:>5}", "x")?;
+            (0..q).try_for_each(|x| write!(f, "{:>w$}", x, w = width))?;
+            write!(f, "\n{:>5}", "")?;
+            self.failures
+                .iter()
+                .try_for_each(|n| write!(f, "{:>w$}", cell(*n), w = width))?;
+            return writeln!(f);
+        }
+        write!(f, "{:>5}", "a\\b")?;
+        (0..q).try_for_each(|b| write!(f, "{:>w$}", b, w = width))?;
+        writeln!(f)?;
+        for (a, row) in self.failures.chunks(q).enumerate() {
+            write!(f, "{:>5}", a)?;
+            row.iter()
+                .try_for_each(|n| write!(f, "{:>w$}", cell(*n), w = width))?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+// Runs every operator on every input, or pair of inputs, of its domain, and prints the matrices of
+// the failures along with the failure rates and their 95% confidence intervals, so that a parameter
+// set can be certified over its whole domain. Run it with `make test-exhaustive`, the number of
+// runs of each input being given by `DEMO_Z8Z_EXHAUSTIVE_RUNS` (1 by default, which only bounds
+// the failure rates loosely).
+#[test]
+#[ignore]
+fn test_exhaustive() {
+    use zqz::estimator::{wilson_interval, Z_95};
+
+    let runs: usize = std::env::var(EXHAUSTIVE_RUNS_VAR)
+        .ok()
+        .map(|runs| runs.parse().expect("the number of runs must be an integer"))
+        .unwrap_or(1);
+    let sk = test_key();
+
+    let mut failed = Vec::new();
+    let (mut failures, mut total_runs) = (0, 0);
+    for operator in exhaustive_operators().iter() {
+        let matrix = FailureMatrix::new(&sk, operator, runs);
+        print!("{}", matrix);
+        failures += matrix.total();
+        total_runs += matrix.total_runs();
+        if matrix.total() != 0 {
+            failed.push(matrix.name);
+        }
+    }
+    let (low, high) = wilson_interval(failures, total_runs, Z_95);
+    println!(
+        "all operators: {} failure(s) over {} run(s), rate {:.2e}, 95% CI [{:.2e}, {:.2e}]",
+        failures,
+        total_runs,
+        failures as f64 / total_runs as f64,
+        low,
+        high
+    );
+    if !failed.is_empty() {
+        panic!("{} operator(s) failed: {}", failed.len(), failed.join(", "));
+    }
+}
+
+#[test]
+fn test_homomorphic_key() {
+    let sk = test_key();
+
     // let sk = zqz::setup_load();
     let mut cpt: usize = 0;
 
@@ -517,8 +2357,53 @@ fn test_homomorphic_key() {
         cpt += test_max(i, &sk);
         cpt += test_max_cst(i, &sk);
         cpt += test_max_cst_rev(i, &sk);
+        cpt += test_max_translated(i, &sk);
+        cpt += test_translated_operators(i, &sk);
+        cpt += test_eval_poly(i, &sk);
+        cpt += test_eval_poly_sum(i, &sk);
+        cpt += test_dot(i, &sk);
+        cpt += test_apply(i, &sk);
+        cpt += test_eval_many(i, &sk);
+        cpt += test_bool(i, &sk);
+        cpt += test_expr(i, &sk);
+        cpt += test_expr_program(i, &sk);
+        cpt += test_circuit(i, &sk);
+        cpt += test_circuit_optimized(i, &sk);
+        cpt += test_plaintext_differential(i, &sk);
     }
     if cpt != 0 {
         panic!("{} ERROR(S)!", cpt);
     }
 }
+
+#[test]
+fn test_failure_estimator() {
+    use zqz::estimator::{wilson_interval, Estimate, NoiseStats, Operation, Z_95};
+
+    // the wilson interval of a few known cases
+    let (low, high) = wilson_interval(0, 100, Z_95);
+    assert_eq!(low, 0.);
+    assert!((high - 0.037).abs() < 1e-3, "{}", high);
+    let (low, high) = wilson_interval(50, 100, Z_95);
+    assert!((low - 0.404).abs() < 1e-3 && (high - 0.596).abs() < 1e-3);
+    assert_eq!(wilson_interval(10, 10, Z_95).1, 1.);
+    assert_eq!(wilson_interval(0, 0, Z_95), (0., 1.));
+
+    // the statistics of the noise
+    let noise = NoiseStats::new(&[0.25, -0.25]);
+    assert_eq!((noise.mean, noise.std_dev, noise.max), (0., 0.25, 0.25));
+    assert_eq!(NoiseStats::new(&[]), NoiseStats::default());
+
+    // the plaintext backend is noiseless, so the estimates only check the operations
+    for (name, params) in zqz::PRESETS.iter() {
+        let sk = EncryptKey::plaintext(params);
+        for operation in Operation::ALL.iter() {
+            let estimate = Estimate::new(&sk, *operation, 100);
+            assert_eq!(estimate.failures, 0, "{}: {}", name, estimate);
+            assert_eq!(estimate.noise, NoiseStats::default(), "{}", name);
+            let expected = wilson_interval(0, 100, Z_95);
+            assert_eq!(estimate.confidence_interval(), expected);
+            assert_eq!(Operation::from_name(operation.name()), Some(*operation));
+        }
+    }
+}
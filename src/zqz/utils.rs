@@ -1,25 +1,56 @@
 //! A module containing utilities functions and macros.
-use crate::PARAMS;
+use crate::zqz::Parameters;
+use concrete::core_api::math::Random;
+use concrete::crypto_api;
 
-/// Compute the round and then the modulo
-pub(super) fn round_modulo(x: f64) -> f64 {
+/// Returns the encoder used for fresh encryptions of Z/qZ messages with these parameters.
+pub(super) fn new_encoder(params: &Parameters) -> crypto_api::Encoder {
+    crypto_api::Encoder::new_rounding_context(
+        0.,
+        params.max,
+        params.nb_bit_precision,
+        params.nb_bit_padding,
+    )
+    .unwrap()
+}
+
+/// Draws a random identifier.
+pub(super) fn random_id() -> u64 {
+    let mut rs = vec![0_u64; 1];
+    Random::rng_uniform(&mut rs);
+    rs[0]
+}
+
+/// Returns a deterministic random generator, for tests only.
+#[cfg(test)]
+pub(super) fn seeded_rng(seed: u64) -> rand_chacha::ChaCha20Rng {
+    use rand_core::SeedableRng;
+    rand_chacha::ChaCha20Rng::seed_from_u64(seed)
+}
+
+/// Compute the round and then the modulo q
+pub(super) fn round_modulo(x: f64, modulo: usize) -> f64 {
     let tmp = (x.round()) as i32;
-    let i: i32 = tmp % (PARAMS.modulo as i32);
-    let res = if i < 0 { i + (PARAMS.modulo as i32) } else { i };
+    let i: i32 = tmp % (modulo as i32);
+    let res = if i < 0 { i + (modulo as i32) } else { i };
     res as f64
 }
 
-/// compute the floor and then the modulo
-pub(super) fn floor_modulo(x: f64) -> f64 {
-    let tmp = x % (PARAMS.modulo as f64);
-    let res = if tmp < 0. {
-        tmp + PARAMS.modulo as f64
-    } else {
-        tmp
-    };
+/// compute the floor and then the modulo q
+pub(super) fn floor_modulo(x: f64, modulo: usize) -> f64 {
+    let tmp = x % (modulo as f64);
+    let res = if tmp < 0. { tmp + modulo as f64 } else { tmp };
     res.floor()
 }
 
+/// Evaluates the polynomial with coefficients `coefs` (constant term first) at `x`, modulo q.
+pub(super) fn poly_modulo(coefs: &[usize], x: usize, modulo: usize) -> usize {
+    coefs
+        .iter()
+        .rev()
+        .fold(0, |acc, c| (acc * x + c % modulo) % modulo)
+}
+
 /// compute the relu
 pub(super) fn relu(x: f64) -> f64 {
     f64::max(0., x)